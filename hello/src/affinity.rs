@@ -0,0 +1,182 @@
+use std::io;
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        ffi::{c_int, c_uint},
+        io, mem,
+    };
+
+    /// Matches glibc's `cpu_set_t`, a bit per CPU for up to 1024 CPUs.
+    #[repr(C)]
+    pub(super) struct CpuSet {
+        bits: [u64; 16],
+    }
+
+    pub(super) const MAX_CPUS: usize = mem::size_of::<CpuSet>() * 8;
+
+    const PRIO_PROCESS: c_int = 0;
+
+    unsafe extern "C" {
+        fn sched_setaffinity(pid: c_int, cpusetsize: usize, mask: *const CpuSet) -> c_int;
+        fn sched_getaffinity(pid: c_int, cpusetsize: usize, mask: *mut CpuSet) -> c_int;
+        safe fn setpriority(which: c_int, who: c_uint, prio: c_int) -> c_int;
+    }
+
+    impl CpuSet {
+        pub(super) fn empty() -> CpuSet {
+            CpuSet { bits: [0; 16] }
+        }
+
+        /// `cpu` must be below [`MAX_CPUS`].
+        pub(super) fn insert(&mut self, cpu: usize) {
+            self.bits[cpu / 64] |= 1 << (cpu % 64);
+        }
+
+        pub(super) fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+            (0..MAX_CPUS).filter(|&cpu| self.bits[cpu / 64] & (1 << (cpu % 64)) != 0)
+        }
+    }
+
+    /// Restricts the calling thread to the CPUs in `set`.
+    pub(super) fn set_affinity(set: &CpuSet) -> io::Result<()> {
+        // SAFETY: pid 0 is the calling thread, and the size passed is the size of the set the
+        // pointer refers to, which lives until the call returns
+        let result = unsafe { sched_setaffinity(0, mem::size_of::<CpuSet>(), set) };
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// CPUs the calling thread is allowed to run on.
+    pub(super) fn affinity() -> io::Result<CpuSet> {
+        let mut set = CpuSet::empty();
+
+        // SAFETY: pid 0 is the calling thread, and the kernel writes at most the size passed,
+        // which is the size of `set`
+        let result = unsafe { sched_getaffinity(0, mem::size_of::<CpuSet>(), &mut set) };
+
+        if result == 0 {
+            Ok(set)
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+
+    /// Sets the nice level of the calling thread.
+    pub(super) fn set_nice(nice: i32) -> io::Result<()> {
+        // Linux keeps nice levels per thread, and `who` 0 means the calling one
+        if setpriority(PRIO_PROCESS, 0, nice) == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Pins the calling thread to the CPUs in `cores`.
+///
+/// Cores the kernel can't represent are rejected here, the kernel itself rejects a mask with no
+/// usable cores in it, including an empty one.
+#[cfg(target_os = "linux")]
+pub(crate) fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    let mut set = sys::CpuSet::empty();
+
+    for &core in cores {
+        if core >= sys::MAX_CPUS {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "core {core} is past the last supported core {}",
+                    sys::MAX_CPUS - 1
+                ),
+            ));
+        }
+
+        set.insert(core);
+    }
+
+    sys::set_affinity(&set)
+}
+
+/// Cores the calling thread may run on, in ascending order.
+#[cfg(target_os = "linux")]
+pub(crate) fn allowed_cores() -> io::Result<Vec<usize>> {
+    Ok(sys::affinity()?.cpus().collect())
+}
+
+/// Sets the nice level of the calling thread, lower runs sooner. Going below the current level
+/// needs `CAP_SYS_NICE`.
+#[cfg(target_os = "linux")]
+pub(crate) fn set_current_nice(nice: i32) -> io::Result<()> {
+    sys::set_nice(nice)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pin_current_thread(_cores: &[usize]) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn allowed_cores() -> io::Result<Vec<usize>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn set_current_nice(_nice: i32) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::{PoolCreationError, ThreadPool};
+    use std::ffi::{c_int, c_uint};
+
+    unsafe extern "C" {
+        safe fn getpriority(which: c_int, who: c_uint) -> c_int;
+    }
+
+    #[test]
+    fn workers_are_pinned_and_reniced() {
+        let first = allowed_cores().unwrap()[0];
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .pin_to_cores([first])
+            .nice(5)
+            .build()
+            .unwrap();
+
+        let handle = pool.spawn(|| (allowed_cores().unwrap(), getpriority(0, 0)));
+        let (cores, nice) = handle.join().unwrap();
+
+        assert_eq!(cores, [first]);
+        assert_eq!(nice, 5);
+    }
+
+    #[test]
+    fn rejected_masks_fail_the_build() {
+        for cores in [vec![], vec![sys::MAX_CPUS], vec![sys::MAX_CPUS - 1]] {
+            let result = ThreadPool::builder()
+                .num_threads(2)
+                .pin_to_cores(cores)
+                .build();
+
+            assert!(matches!(
+                result,
+                Err(PoolCreationError::Affinity { id: 0, .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn one_worker_per_allowed_core() {
+        let cores = allowed_cores().unwrap();
+        let pool = ThreadPool::builder().pin_per_core().build().unwrap();
+
+        assert_eq!(pool.workers.len(), cores.len());
+    }
+}
@@ -0,0 +1,205 @@
+//! Compares `hello::ThreadPool` against the original single-lock design, where every worker
+//! blocks on one `Arc<Mutex<mpsc::Receiver<Job>>>`.
+//!
+//! Run with `cargo run --release --bin pool_bench [jobs] [threads]`.
+
+use std::{
+    env, process,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use hello::ThreadPool;
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Child jobs each fan-out parent submits.
+const CHILDREN: usize = 64;
+
+trait Pool: Send + Sync + 'static {
+    fn execute_job(&self, job: Job);
+}
+
+impl Pool for ThreadPool {
+    fn execute_job(&self, job: Job) {
+        self.execute(job);
+    }
+}
+
+/// The thread pool as it was before the work-stealing queue.
+struct LockedPool {
+    workers: Vec<thread::JoinHandle<()>>,
+    sender: Option<mpsc::Sender<Job>>,
+}
+
+impl LockedPool {
+    fn new(size: usize) -> LockedPool {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+
+                thread::spawn(move || {
+                    loop {
+                        let message = receiver.lock().unwrap().recv();
+
+                        match message {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        LockedPool {
+            workers,
+            sender: Some(sender),
+        }
+    }
+}
+
+impl Pool for LockedPool {
+    fn execute_job(&self, job: Job) {
+        self.sender.as_ref().unwrap().send(job).unwrap();
+    }
+}
+
+impl Drop for LockedPool {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+
+        for worker in self.workers.drain(..) {
+            worker.join().unwrap();
+        }
+    }
+}
+
+/// Counts finished jobs and wakes the benchmark thread once all of them are in.
+struct Countdown {
+    done: AtomicUsize,
+    total: usize,
+    finished: Mutex<Option<mpsc::Sender<()>>>,
+}
+
+impl Countdown {
+    fn new(total: usize) -> (Arc<Countdown>, mpsc::Receiver<()>) {
+        let (sender, receiver) = mpsc::channel();
+
+        let countdown = Countdown {
+            done: AtomicUsize::new(0),
+            total,
+            finished: Mutex::new(Some(sender)),
+        };
+
+        (Arc::new(countdown), receiver)
+    }
+
+    fn tick(&self) {
+        if self.done.fetch_add(1, Ordering::AcqRel) + 1 == self.total
+            && let Some(sender) = self.finished.lock().unwrap().take()
+        {
+            sender.send(()).unwrap();
+        }
+    }
+}
+
+/// Submits `jobs` tiny jobs from the benchmark thread.
+fn flat<P: Pool>(pool: &P, jobs: usize) -> Duration {
+    let (countdown, finished) = Countdown::new(jobs);
+    let start = Instant::now();
+
+    for _ in 0..jobs {
+        let countdown = Arc::clone(&countdown);
+        pool.execute_job(Box::new(move || countdown.tick()));
+    }
+
+    finished.recv().unwrap();
+    start.elapsed()
+}
+
+/// Submits `parents` parent jobs that each fan out into [`CHILDREN`] tiny child jobs from
+/// inside the pool.
+fn fan_out<P: Pool>(pool: &Arc<P>, parents: usize) -> Duration {
+    // Parents tick too, after dropping their handle on the pool, so the benchmark thread always
+    // holds the last reference and the pool is never dropped from one of its own workers
+    let (countdown, finished) = Countdown::new(parents * (CHILDREN + 1));
+    let start = Instant::now();
+
+    for _ in 0..parents {
+        let pool_ref = Arc::clone(pool);
+        let countdown = Arc::clone(&countdown);
+
+        pool.execute_job(Box::new(move || {
+            for _ in 0..CHILDREN {
+                let countdown = Arc::clone(&countdown);
+                pool_ref.execute_job(Box::new(move || countdown.tick()));
+            }
+
+            drop(pool_ref);
+            countdown.tick();
+        }));
+    }
+
+    finished.recv().unwrap();
+    start.elapsed()
+}
+
+fn report(name: &str, jobs: usize, locked: Duration, stealing: Duration) {
+    let per_job = |d: Duration| d.as_nanos() as f64 / jobs as f64;
+
+    println!(
+        "{name:<8} single lock: {:>9.2?} ({:>7.1} ns/job)   work stealing: {:>9.2?} ({:>7.1} ns/job)   speedup: {:.2}x",
+        locked,
+        per_job(locked),
+        stealing,
+        per_job(stealing),
+        locked.as_secs_f64() / stealing.as_secs_f64(),
+    );
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+
+    let jobs = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .unwrap_or(200_000);
+    let threads = args
+        .next()
+        .and_then(|arg| arg.parse().ok())
+        .or_else(|| thread::available_parallelism().ok().map(|n| n.get()))
+        .unwrap_or(4);
+
+    if jobs == 0 || threads == 0 {
+        eprintln!("usage: pool_bench [jobs] [threads], both greater than zero");
+        process::exit(2);
+    }
+
+    println!("{jobs} jobs on {threads} threads");
+
+    let locked = Arc::new(LockedPool::new(threads));
+    let stealing = Arc::new(ThreadPool::build(threads).expect("failed to build pool"));
+
+    // Warm both pools up so thread start-up isn't measured
+    flat(&*locked, 1_000);
+    flat(&*stealing, 1_000);
+
+    report("flat", jobs, flat(&*locked, jobs), flat(&*stealing, jobs));
+
+    // Rounded up so small job counts still get a parent, which counts as a job itself
+    let parents = jobs.div_ceil(CHILDREN);
+    report(
+        "fan-out",
+        parents * (CHILDREN + 1),
+        fan_out(&locked, parents),
+        fan_out(&stealing, parents),
+    );
+}
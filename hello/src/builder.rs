@@ -0,0 +1,400 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    num::NonZero,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use crate::{
+    NoopObserver, OverflowPolicy, PoolCreationError, PoolObserver, Shared, ThreadPool, Worker,
+    affinity,
+    group::Latch,
+    handle::panic_message,
+    queue::{DEFAULT_AGING, JobQueue},
+    stats::Metrics,
+};
+
+type Hook = Box<dyn Fn(usize) + Send + Sync + 'static>;
+type Init = Box<dyn Fn(usize) -> Box<dyn Any> + Send + Sync + 'static>;
+
+thread_local! {
+    /// State created by the pool's worker init closure for the worker running on this thread.
+    static WORKER_STATE: RefCell<Option<Box<dyn Any>>> = const { RefCell::new(None) };
+}
+
+/// Configures and creates a [`ThreadPool`].
+///
+/// Start from [`ThreadPool::builder`], chain the settings to change and finish with
+/// [`ThreadPoolBuilder::build`].
+pub struct ThreadPoolBuilder {
+    num_threads: Option<usize>,
+    queue_capacity: Option<usize>,
+    overflow_policy: OverflowPolicy,
+    priority_aging: Duration,
+    observer: Arc<dyn PoolObserver>,
+    pin_per_core: bool,
+    config: WorkerConfig,
+}
+
+/// Settings each worker thread is started with, kept around for respawned workers.
+#[derive(Default)]
+pub(crate) struct WorkerConfig {
+    name_prefix: Option<String>,
+    stack_size: Option<usize>,
+    on_start: Option<Hook>,
+    on_stop: Option<Hook>,
+    init: Option<Init>,
+    cores: Option<Vec<usize>>,
+    nice: Option<i32>,
+}
+
+impl WorkerConfig {
+    pub(crate) fn thread_builder(&self, id: usize) -> thread::Builder {
+        let mut builder = thread::Builder::new();
+
+        if let Some(prefix) = &self.name_prefix {
+            builder = builder.name(format!("{prefix}{id}"));
+        }
+
+        if let Some(size) = self.stack_size {
+            builder = builder.stack_size(size);
+        }
+
+        builder
+    }
+
+    /// Applies the core pinning and nice level on the worker thread, before the start hook.
+    pub(crate) fn place_thread(&self, id: usize) -> Result<(), PoolCreationError> {
+        if let Some(cores) = &self.cores {
+            // Worker `id` gets one core, round-robin over the list, an empty list goes through
+            // as an empty mask for the kernel to reject
+            let core = cores.get(id % cores.len().max(1)).copied();
+
+            affinity::pin_current_thread(core.as_slice())
+                .map_err(|source| PoolCreationError::Affinity { id, source })?;
+        }
+
+        if let Some(nice) = self.nice {
+            affinity::set_current_nice(nice)
+                .map_err(|source| PoolCreationError::Nice { id, source })?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs on the worker thread before it takes its first job, a panic in the start hook or
+    /// the init closure comes back as [`PoolCreationError::WorkerStart`].
+    pub(crate) fn thread_started(&self, id: usize) -> Result<(), PoolCreationError> {
+        panic::catch_unwind(AssertUnwindSafe(|| {
+            if let Some(on_start) = &self.on_start {
+                on_start(id);
+            }
+
+            if let Some(init) = &self.init {
+                let state = init(id);
+                WORKER_STATE.with(|slot| *slot.borrow_mut() = Some(state));
+            }
+        }))
+        .map_err(|payload| PoolCreationError::WorkerStart {
+            id,
+            message: panic_message(payload.as_ref()).map(String::from),
+        })
+    }
+
+    /// Runs on the worker thread once it's done taking jobs.
+    pub(crate) fn thread_stopped(&self, id: usize) {
+        if let Some(on_stop) = &self.on_stop {
+            on_stop(id);
+        }
+    }
+}
+
+impl Default for ThreadPoolBuilder {
+    fn default() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
+}
+
+impl ThreadPoolBuilder {
+    /// Starts from the defaults: one thread per available CPU, an unbounded queue, and unnamed
+    /// threads with the default stack size.
+    pub fn new() -> ThreadPoolBuilder {
+        ThreadPoolBuilder {
+            num_threads: None,
+            queue_capacity: None,
+            overflow_policy: OverflowPolicy::Block,
+            priority_aging: DEFAULT_AGING,
+            observer: Arc::new(NoopObserver),
+            pin_per_core: false,
+            config: WorkerConfig::default(),
+        }
+    }
+
+    /// Sets the number of worker threads.
+    pub fn num_threads(mut self, num_threads: usize) -> ThreadPoolBuilder {
+        self.num_threads = Some(num_threads);
+        self
+    }
+
+    /// Bounds the queue to `capacity` waiting jobs, see [`ThreadPool::build_bounded`].
+    pub fn queue_capacity(mut self, capacity: usize) -> ThreadPoolBuilder {
+        self.queue_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets what [`ThreadPool::execute`] does when a bounded queue is full.
+    pub fn overflow_policy(mut self, policy: OverflowPolicy) -> ThreadPoolBuilder {
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Sets how long a queued job waits before it's treated like a job one
+    /// [`Priority`](crate::Priority) level up, 500ms by default.
+    pub fn priority_aging(mut self, step: Duration) -> ThreadPoolBuilder {
+        self.priority_aging = step;
+        self
+    }
+
+    /// Sends job and worker events to `observer` instead of discarding them.
+    pub fn observer(mut self, observer: Arc<dyn PoolObserver>) -> ThreadPoolBuilder {
+        self.observer = observer;
+        self
+    }
+
+    /// Names worker threads `prefix` followed by the worker id, so panics and profilers show
+    /// e.g. `hello-worker-3` instead of `<unnamed>`.
+    pub fn thread_name(mut self, prefix: impl Into<String>) -> ThreadPoolBuilder {
+        self.config.name_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Sets the stack size of worker threads in bytes.
+    pub fn stack_size(mut self, size: usize) -> ThreadPoolBuilder {
+        self.config.stack_size = Some(size);
+        self
+    }
+
+    /// Pins each worker to one of `cores`, worker `i` to `cores[i % cores.len()]`, so with as
+    /// many workers as cores each gets its own. Linux only.
+    ///
+    /// The pinning is done by each worker as it starts, if the kernel rejects it `build` fails
+    /// with [`PoolCreationError::Affinity`].
+    pub fn pin_to_cores(mut self, cores: impl IntoIterator<Item = usize>) -> ThreadPoolBuilder {
+        self.config.cores = Some(cores.into_iter().collect());
+        self.pin_per_core = false;
+        self
+    }
+
+    /// Pins one worker to each core the process is allowed to run on, and unless
+    /// [`ThreadPoolBuilder::num_threads`] is set, starts one worker per such core. Linux only.
+    pub fn pin_per_core(mut self) -> ThreadPoolBuilder {
+        self.config.cores = None;
+        self.pin_per_core = true;
+        self
+    }
+
+    /// Sets the nice level of worker threads, from -20 (most favourable) to 19. Linux only.
+    ///
+    /// Going below the nice level of the thread calling `build` needs `CAP_SYS_NICE`, without
+    /// it `build` fails with [`PoolCreationError::Nice`].
+    pub fn nice(mut self, nice: i32) -> ThreadPoolBuilder {
+        self.config.nice = Some(nice);
+        self
+    }
+
+    /// Calls `f` with the worker id on each worker thread when it starts, including threads
+    /// that replace a worker that died.
+    ///
+    /// If `f` panics, `build` fails with [`PoolCreationError::WorkerStart`].
+    pub fn on_thread_start<F>(mut self, f: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.config.on_start = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the worker id on each worker thread when it stops.
+    pub fn on_thread_stop<F>(mut self, f: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize) + Send + Sync + 'static,
+    {
+        self.config.on_stop = Some(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the worker id on each worker thread when it starts, and keeps what it
+    /// returns in a thread-local that jobs can reach through [`with_worker_state`].
+    ///
+    /// If `f` panics, `build` fails with [`PoolCreationError::WorkerStart`].
+    pub fn worker_init<F, S>(mut self, f: F) -> ThreadPoolBuilder
+    where
+        F: Fn(usize) -> S + Send + Sync + 'static,
+        S: 'static,
+    {
+        self.config.init = Some(Box::new(move |id| Box::new(f(id))));
+        self
+    }
+
+    /// Creates the pool.
+    ///
+    /// Fails with [`PoolCreationError::ZeroSize`] if the number of threads is zero,
+    /// [`PoolCreationError::ZeroCapacity`] if the queue capacity is zero,
+    /// [`PoolCreationError::Spawn`] if a worker thread can't be spawned and
+    /// [`PoolCreationError::Affinity`] or [`PoolCreationError::Nice`] if the kernel rejects a
+    /// worker's pinning or nice level and [`PoolCreationError::WorkerStart`] if the start hook
+    /// or init closure panics, any workers that were already started are shut down and joined
+    /// before the error is returned
+    pub fn build(mut self) -> Result<ThreadPool, PoolCreationError> {
+        if self.pin_per_core {
+            let cores = affinity::allowed_cores()
+                .map_err(|source| PoolCreationError::Affinity { id: 0, source })?;
+
+            self.num_threads.get_or_insert(cores.len());
+            self.config.cores = Some(cores);
+        }
+
+        let size = self
+            .num_threads
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZero::get));
+
+        if size == 0 {
+            return Err(PoolCreationError::ZeroSize);
+        }
+
+        if self.queue_capacity == Some(0) {
+            return Err(PoolCreationError::ZeroCapacity);
+        }
+
+        let shared = Arc::new(Shared {
+            queue: JobQueue::new(size, self.queue_capacity, self.priority_aging),
+            policy: self.overflow_policy,
+            config: self.config,
+            observer: self.observer,
+            metrics: Metrics::new(),
+            outstanding: Latch::new(),
+            exited: Mutex::new(vec![false; size]),
+            worker_exited: Condvar::new(),
+        });
+
+        // Dropping a half-built pool closes the queue and joins the workers spawned so far
+        let mut pool = ThreadPool {
+            workers: Vec::with_capacity(size),
+            shared,
+            timer: OnceLock::new(),
+        };
+
+        for id in 0..size {
+            let worker = Worker::new(id, Arc::clone(&pool.shared))?;
+            pool.workers.push(worker);
+        }
+
+        Ok(pool)
+    }
+}
+
+/// Runs `f` with the state created by [`ThreadPoolBuilder::worker_init`] for the current
+/// worker.
+///
+/// Returns `None` when called outside a pool worker, when the state isn't an `S`, or when
+/// called from inside another `with_worker_state` on the same thread.
+pub fn with_worker_state<S, R, F>(f: F) -> Option<R>
+where
+    S: 'static,
+    F: FnOnce(&mut S) -> R,
+{
+    WORKER_STATE.with(|slot| {
+        let mut slot = slot.try_borrow_mut().ok()?;
+        let state = slot.as_mut()?.downcast_mut::<S>()?;
+
+        Some(f(state))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lock, with_worker_state};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc,
+    };
+
+    #[test]
+    fn builder_names_threads_and_runs_hooks() {
+        let (events_tx, events_rx) = mpsc::channel();
+        let start_tx = Mutex::new(events_tx.clone());
+        let stop_tx = Mutex::new(events_tx);
+
+        let pool = ThreadPool::builder()
+            .num_threads(2)
+            .thread_name("test-worker-")
+            .on_thread_start(move |id| lock(&start_tx).send(("start", id)).unwrap())
+            .on_thread_stop(move |id| lock(&stop_tx).send(("stop", id)).unwrap())
+            .worker_init(|id| vec![id])
+            .build()
+            .unwrap();
+
+        let name = pool.spawn(|| thread::current().name().map(String::from));
+        assert!(name.join().unwrap().unwrap().starts_with("test-worker-"));
+
+        let state = pool.spawn(|| {
+            with_worker_state(|seen: &mut Vec<usize>| {
+                seen.push(100);
+                seen.len()
+            })
+        });
+        assert_eq!(state.join().unwrap(), Some(2));
+        assert_eq!(with_worker_state(|_: &mut Vec<usize>| ()), None);
+
+        drop(pool);
+
+        let mut events: Vec<_> = events_rx.iter().collect();
+        events.sort();
+        assert_eq!(
+            events,
+            vec![("start", 0), ("start", 1), ("stop", 0), ("stop", 1)]
+        );
+    }
+
+    #[test]
+    fn panicking_start_hooks_fail_the_build() {
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&stopped);
+
+        let Err(err) = ThreadPool::builder()
+            .num_threads(2)
+            .on_thread_start(|id| {
+                if id == 1 {
+                    panic!("no start");
+                }
+            })
+            .on_thread_stop(move |_| {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })
+            .build()
+        else {
+            panic!("a panicking start hook must fail the build");
+        };
+
+        assert!(matches!(err, PoolCreationError::WorkerStart { id: 1, .. }));
+        assert_eq!(
+            err.to_string(),
+            "worker 1 panicked while starting: no start"
+        );
+        // Worker 0 started fine and has been stopped again by the time `build` returns
+        assert_eq!(stopped.load(Ordering::SeqCst), 1);
+
+        let Err(err) = ThreadPool::builder()
+            .num_threads(1)
+            .worker_init(|_| -> usize { panic!("no state") })
+            .build()
+        else {
+            panic!("a panicking init closure must fail the build");
+        };
+        assert!(matches!(err, PoolCreationError::WorkerStart { id: 0, .. }));
+    }
+}
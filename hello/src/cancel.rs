@@ -0,0 +1,90 @@
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+
+use crate::{ThreadPool, queue::TaskOptions};
+
+/// Shared flag for withdrawing a job, returned by [`ThreadPool::execute_cancellable`].
+///
+/// Cancelling a job that hasn't started drops it without running it. A job that's already
+/// running gets a clone of the token and can check [`CancelToken::is_cancelled`] to stop early.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    /// Creates a token that isn't cancelled yet.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels every job holding a clone of this token.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` once [`CancelToken::cancel`] has been called on any clone of this token.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+impl ThreadPool {
+    /// Queues `f` and returns a [`CancelToken`] that withdraws it.
+    ///
+    /// If the token is cancelled before a worker picks the job up, the job is dropped without
+    /// running and counted in [`PoolStats::cancelled_jobs`](crate::PoolStats::cancelled_jobs).
+    /// Once running, `f` is responsible for polling the token it's given.
+    pub fn execute_cancellable<F>(&self, f: F) -> CancelToken
+    where
+        F: FnOnce(&CancelToken) + Send + 'static,
+    {
+        let token = CancelToken::new();
+        let job_token = token.clone();
+
+        self.shared.execute_with(
+            move || f(&job_token),
+            TaskOptions {
+                cancel: Some(token.clone()),
+                ..TaskOptions::default()
+            },
+        );
+
+        token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread, time::Duration};
+
+    #[test]
+    fn cancelled_jobs_are_dropped_or_see_the_token() {
+        let pool = ThreadPool::build(1).unwrap();
+        let (started_tx, started_rx) = mpsc::channel();
+        let (stopped_tx, stopped_rx) = mpsc::channel();
+
+        let running = pool.execute_cancellable(move |token| {
+            started_tx.send(()).unwrap();
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(1));
+            }
+            stopped_tx.send(()).unwrap();
+        });
+        started_rx.recv().unwrap();
+
+        let queued = pool.execute_cancellable(|_| panic!("cancelled job ran"));
+        queued.cancel();
+        running.cancel();
+
+        stopped_rx.recv().unwrap();
+        pool.spawn(|| {}).join().unwrap();
+
+        let stats = pool.stats();
+        assert_eq!(stats.cancelled_jobs, 1);
+        assert_eq!(stats.panicked_jobs, 0);
+    }
+}
@@ -0,0 +1,169 @@
+use std::{collections::VecDeque, fmt, sync::Mutex};
+
+use crate::{Job, ThreadPool, lock};
+
+/// Something that runs jobs, implemented by [`ThreadPool`] and by [`TestExecutor`].
+///
+/// Code written against `impl Executor` instead of `&ThreadPool` can be handed a
+/// [`TestExecutor`] in tests, which runs jobs on the calling thread in a fixed order.
+pub trait Executor {
+    /// Runs `job` at some point, possibly on another thread.
+    fn execute_job(&self, job: Job);
+
+    /// Runs `f` at some point, possibly on another thread.
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+        Self: Sized,
+    {
+        self.execute_job(Box::new(f));
+    }
+}
+
+impl Executor for ThreadPool {
+    fn execute_job(&self, job: Job) {
+        self.shared.execute(job);
+    }
+}
+
+/// How a [`TestExecutor`] runs the jobs it's given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Inline,
+    Manual,
+}
+
+/// Single-threaded [`Executor`] for tests, which runs jobs in the order they were submitted.
+///
+/// An [`inline`](TestExecutor::inline) executor runs each job on the spot, before `execute`
+/// returns. A [`manual`](TestExecutor::manual) one queues them until the test runs them with
+/// [`TestExecutor::run_next`] or [`TestExecutor::run_all`], so it can check state in between.
+///
+/// Jobs run on whichever thread runs them, and a panicking job panics that thread, which fails
+/// the test rather than being counted like on a [`ThreadPool`].
+pub struct TestExecutor {
+    mode: Mode,
+    queue: Mutex<VecDeque<Job>>,
+}
+
+impl TestExecutor {
+    /// Creates an executor that runs each job as soon as it's submitted.
+    pub fn inline() -> TestExecutor {
+        TestExecutor::with_mode(Mode::Inline)
+    }
+
+    /// Creates an executor that holds jobs until the test runs them.
+    pub fn manual() -> TestExecutor {
+        TestExecutor::with_mode(Mode::Manual)
+    }
+
+    fn with_mode(mode: Mode) -> TestExecutor {
+        TestExecutor {
+            mode,
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Number of jobs waiting to be run.
+    pub fn pending(&self) -> usize {
+        lock(&self.queue).len()
+    }
+
+    /// Runs the oldest waiting job, returns `false` if there wasn't one.
+    pub fn run_next(&self) -> bool {
+        // Not holding the lock while the job runs, it may submit more
+        let job = lock(&self.queue).pop_front();
+
+        match job {
+            Some(job) => {
+                job();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Runs jobs until none are left, including ones submitted by the jobs it runs, and returns
+    /// how many ran.
+    pub fn run_all(&self) -> usize {
+        let mut ran = 0;
+
+        while self.run_next() {
+            ran += 1;
+        }
+
+        ran
+    }
+}
+
+impl Executor for TestExecutor {
+    fn execute_job(&self, job: Job) {
+        match self.mode {
+            Mode::Inline => job(),
+            Mode::Manual => lock(&self.queue).push_back(job),
+        }
+    }
+}
+
+impl fmt::Debug for TestExecutor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestExecutor")
+            .field("mode", &self.mode)
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, mpsc};
+
+    /// Stand-in for code under test that fans work out to an executor.
+    fn record_squares(executor: &impl Executor, n: usize, sender: mpsc::Sender<usize>) {
+        for i in 0..n {
+            let sender = sender.clone();
+            executor.execute(move || sender.send(i * i).unwrap());
+        }
+    }
+
+    #[test]
+    fn inline_executor_runs_jobs_in_submission_order() {
+        let (sender, receiver) = mpsc::channel();
+        record_squares(&TestExecutor::inline(), 4, sender);
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0, 1, 4, 9]);
+    }
+
+    #[test]
+    fn manual_executor_runs_jobs_when_told() {
+        let executor = Arc::new(TestExecutor::manual());
+        let (sender, receiver) = mpsc::channel();
+        record_squares(executor.as_ref(), 3, sender.clone());
+
+        assert_eq!(executor.pending(), 3);
+        assert!(receiver.try_recv().is_err());
+
+        assert!(executor.run_next());
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [0]);
+
+        // A job that submits another, the nested one goes to the back of the queue
+        let nested = Arc::clone(&executor);
+        executor.execute(move || record_squares(nested.as_ref(), 1, sender));
+
+        assert_eq!(executor.run_all(), 4);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [1, 4, 0]);
+        assert!(!executor.run_next());
+    }
+
+    #[test]
+    fn thread_pool_is_an_executor() {
+        let pool = ThreadPool::new(2);
+        let (sender, receiver) = mpsc::channel();
+        record_squares(&pool, 4, sender);
+
+        let mut squares: Vec<_> = receiver.iter().collect();
+        squares.sort_unstable();
+        assert_eq!(squares, [0, 1, 4, 9]);
+    }
+}
@@ -0,0 +1,299 @@
+use std::{
+    future::Future,
+    panic::{self, AssertUnwindSafe},
+    pin::pin,
+    sync::{
+        Arc, Mutex, Weak,
+        atomic::{AtomicU8, Ordering},
+        mpsc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+};
+
+use crate::{JobHandle, Shared, ThreadPool, lock};
+
+type PollFn = Box<dyn FnMut(&mut Context<'_>) -> Poll<()> + Send>;
+
+// Where a future is between polls, so a wake while it's queued or running doesn't queue it twice
+const IDLE: u8 = 0;
+const SCHEDULED: u8 = 1;
+const RUNNING: u8 = 2;
+const NOTIFIED: u8 = 3;
+const DONE: u8 = 4;
+
+/// A future spawned on the pool, each wake queues one job that polls it.
+struct FutureTask {
+    poll: Mutex<Option<PollFn>>,
+    state: AtomicU8,
+    pool: Weak<Shared>,
+}
+
+impl FutureTask {
+    /// Queues a poll, or gives up on the future if the pool is gone.
+    fn schedule(self: &Arc<Self>) {
+        match self.pool.upgrade() {
+            Some(shared) => {
+                let poll = ScheduledPoll(Some(Arc::clone(self)));
+                shared.execute(move || poll.run());
+            }
+            None => self.cancel(),
+        }
+    }
+
+    fn run(self: Arc<Self>) {
+        self.state.store(RUNNING, Ordering::SeqCst);
+
+        let waker = Waker::from(Arc::clone(&self));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut slot = lock(&self.poll);
+        let Some(poll) = slot.as_mut() else {
+            return;
+        };
+
+        if poll(&mut cx).is_ready() {
+            *slot = None;
+            self.state.store(DONE, Ordering::SeqCst);
+            return;
+        }
+        drop(slot);
+
+        // Woken while it was being polled, the wake left it to us to queue it again
+        if self
+            .state
+            .compare_exchange(RUNNING, IDLE, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            self.state.store(SCHEDULED, Ordering::SeqCst);
+            self.schedule();
+        }
+    }
+
+    /// Drops the future, which drops the sender so its handle reports
+    /// [`JobError::Cancelled`](crate::JobError::Cancelled).
+    fn cancel(&self) {
+        self.state.store(DONE, Ordering::SeqCst);
+        lock(&self.poll).take();
+    }
+}
+
+impl Wake for FutureTask {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let mut state = self.state.load(Ordering::SeqCst);
+
+        loop {
+            let next = match state {
+                IDLE => SCHEDULED,
+                RUNNING => NOTIFIED,
+                _ => return,
+            };
+
+            match self
+                .state
+                .compare_exchange(state, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) if next == SCHEDULED => return self.schedule(),
+                Ok(_) => return,
+                Err(actual) => state = actual,
+            }
+        }
+    }
+}
+
+/// The job queued for one poll. If the queue drops it without running it, for example because
+/// it's full or the pool is shutting down, the future is dropped with it rather than left
+/// waiting for a poll that will never come.
+struct ScheduledPoll(Option<Arc<FutureTask>>);
+
+impl ScheduledPoll {
+    fn run(mut self) {
+        if let Some(task) = self.0.take() {
+            task.run();
+        }
+    }
+}
+
+impl Drop for ScheduledPoll {
+    fn drop(&mut self) {
+        if let Some(task) = self.0.take() {
+            task.cancel();
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Runs `future` on the pool's workers and returns a handle to its output.
+    ///
+    /// The future is polled by a job on the queue, and each time its waker is woken another job
+    /// is queued to poll it again, so a pending future doesn't hold a worker. Those jobs go
+    /// through the queue like any other and follow the pool's [`OverflowPolicy`]; if one is
+    /// dropped, or the pool is dropped while the future is pending, the future is dropped and
+    /// the handle reports [`JobError::Cancelled`] once it's woken.
+    ///
+    /// A panic while polling is caught and reported through the handle, like with
+    /// [`ThreadPool::spawn`].
+    ///
+    /// [`OverflowPolicy`]: crate::OverflowPolicy
+    /// [`JobError::Cancelled`]: crate::JobError::Cancelled
+    pub fn spawn_future<F>(&self, future: F) -> JobHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let mut future = Box::pin(future);
+
+        let poll: PollFn = Box::new(move |cx| {
+            let result = match panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+                Ok(Poll::Pending) => return Poll::Pending,
+                Ok(Poll::Ready(output)) => Ok(output),
+                Err(payload) => Err(payload),
+            };
+
+            // The caller may have dropped the handle, nobody is left to tell
+            let _ = sender.send(result);
+            Poll::Ready(())
+        });
+
+        let task = Arc::new(FutureTask {
+            poll: Mutex::new(Some(poll)),
+            state: AtomicU8::new(SCHEDULED),
+            pool: Arc::downgrade(&self.shared),
+        });
+        task.schedule();
+
+        JobHandle::new(receiver)
+    }
+}
+
+/// Wakes a thread blocked in [`block_on`].
+struct ThreadWaker(Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Runs `future` to completion on the current thread, parking the thread while it's pending.
+///
+/// Calling this from a job blocks that worker until the future finishes, so a future that
+/// waits on other jobs in the same pool can deadlock a small pool.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+
+        // Wakes that came in while polling leave the token set, so this returns straight away
+        thread::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::JobError;
+    use std::{pin::Pin, time::Duration};
+
+    /// Pending until a helper thread wakes it after `delay`.
+    struct Delay {
+        delay: Duration,
+        fired: Arc<Mutex<bool>>,
+        started: bool,
+    }
+
+    impl Delay {
+        fn new(delay: Duration) -> Delay {
+            Delay {
+                delay,
+                fired: Arc::new(Mutex::new(false)),
+                started: false,
+            }
+        }
+    }
+
+    impl Future for Delay {
+        type Output = ();
+
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+            if *lock(&self.fired) {
+                return Poll::Ready(());
+            }
+
+            if !self.started {
+                self.started = true;
+
+                let (delay, fired, waker) =
+                    (self.delay, Arc::clone(&self.fired), cx.waker().clone());
+                thread::spawn(move || {
+                    thread::sleep(delay);
+                    *lock(&fired) = true;
+                    waker.wake();
+                });
+            }
+
+            Poll::Pending
+        }
+    }
+
+    #[test]
+    fn spawned_futures_run_to_completion_on_the_pool() {
+        let pool = ThreadPool::new(2);
+
+        let handles: Vec<_> = (0..4u64)
+            .map(|i| {
+                pool.spawn_future(async move {
+                    Delay::new(Duration::from_millis(10 * i)).await;
+                    Delay::new(Duration::from_millis(5)).await;
+                    i * 2
+                })
+            })
+            .collect();
+        let panicked = pool.spawn_future(async {
+            Delay::new(Duration::from_millis(5)).await;
+            panic!("future failed");
+        });
+
+        let values: Vec<u64> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(values, [0, 2, 4, 6]);
+        assert!(panicked.join().unwrap_err().is_panic());
+    }
+
+    #[test]
+    fn futures_pending_when_the_pool_drops_are_cancelled() {
+        let pool = ThreadPool::new(1);
+        let delay = Delay::new(Duration::from_millis(100));
+        let handle = pool.spawn_future(delay);
+
+        // Let the first poll start the delay, then drop the pool before it fires
+        thread::sleep(Duration::from_millis(20));
+        drop(pool);
+
+        assert!(matches!(handle.join(), Err(JobError::Cancelled)));
+    }
+
+    #[test]
+    fn block_on_waits_for_wakes_from_other_threads() {
+        let value = block_on(async {
+            Delay::new(Duration::from_millis(10)).await;
+            7
+        });
+
+        assert_eq!(value, 7);
+    }
+}
@@ -0,0 +1,394 @@
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+};
+
+use crate::{Job, JobError, Shared, ThreadPool, group::Latch, lock};
+
+/// Identifies a task in the [`TaskGraph`] that returned it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TaskId(usize);
+
+impl TaskId {
+    /// Position of the task in the order it was added, starting at 0.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// Set of jobs and the order they have to run in, run with [`ThreadPool::run_graph`].
+///
+/// Each task starts as soon as every task it depends on has finished, so independent branches
+/// run in parallel. A task that panics fails, and everything downstream of it is skipped.
+#[derive(Default)]
+pub struct TaskGraph {
+    tasks: Vec<Job>,
+    successors: Vec<Vec<usize>>,
+}
+
+impl TaskGraph {
+    /// Creates an empty graph.
+    pub fn new() -> TaskGraph {
+        TaskGraph::default()
+    }
+
+    /// Adds a task, which runs once the tasks it's made to depend on have finished.
+    pub fn add_task<F>(&mut self, f: F) -> TaskId
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.tasks.push(Box::new(f));
+        self.successors.push(Vec::new());
+
+        TaskId(self.tasks.len() - 1)
+    }
+
+    /// Makes `after` wait for `before` to finish.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either id wasn't returned by this graph's [`TaskGraph::add_task`].
+    pub fn add_dependency(&mut self, before: TaskId, after: TaskId) {
+        assert!(
+            before.0 < self.tasks.len() && after.0 < self.tasks.len(),
+            "task id from another graph"
+        );
+
+        self.successors[before.0].push(after.0);
+    }
+
+    /// Number of tasks in the graph.
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// Returns `true` if no tasks have been added.
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    /// Number of unfinished predecessors each task starts with.
+    fn predecessors(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.tasks.len()];
+
+        for &next in self.successors.iter().flatten() {
+            counts[next] += 1;
+        }
+
+        counts
+    }
+
+    /// Walks the graph in dependency order; tasks the walk never reaches are on or behind a
+    /// cycle.
+    fn check_acyclic(&self, predecessors: &[usize]) -> Result<(), GraphCycle> {
+        let mut waiting = predecessors.to_vec();
+        let mut ready: VecDeque<usize> = (0..waiting.len()).filter(|&i| waiting[i] == 0).collect();
+        let mut reached = vec![false; waiting.len()];
+
+        while let Some(id) = ready.pop_front() {
+            reached[id] = true;
+
+            for &next in &self.successors[id] {
+                waiting[next] -= 1;
+                if waiting[next] == 0 {
+                    ready.push_back(next);
+                }
+            }
+        }
+
+        let tasks: Vec<TaskId> = (0..reached.len())
+            .filter(|&i| !reached[i])
+            .map(TaskId)
+            .collect();
+
+        if tasks.is_empty() {
+            Ok(())
+        } else {
+            Err(GraphCycle { tasks })
+        }
+    }
+}
+
+impl fmt::Debug for TaskGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaskGraph")
+            .field("tasks", &self.tasks.len())
+            .field("successors", &self.successors)
+            .finish()
+    }
+}
+
+/// Error returned by [`ThreadPool::run_graph`] when the tasks' dependencies form a cycle.
+///
+/// Nothing in the graph is run.
+#[derive(Debug)]
+pub struct GraphCycle {
+    tasks: Vec<TaskId>,
+}
+
+impl GraphCycle {
+    /// Tasks that could never start, because they're on a cycle or depend on one that is.
+    pub fn tasks(&self) -> &[TaskId] {
+        &self.tasks
+    }
+}
+
+impl fmt::Display for GraphCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "task graph has a dependency cycle, {} tasks could never start",
+            self.tasks.len()
+        )
+    }
+}
+
+impl Error for GraphCycle {}
+
+/// How each task in a graph run by [`ThreadPool::run_graph`] went.
+#[derive(Debug)]
+pub struct GraphReport {
+    outcomes: Vec<Result<(), JobError>>,
+}
+
+impl GraphReport {
+    /// Result of one task: `Ok` if it ran to completion, [`JobError::Panicked`] if it panicked,
+    /// and [`JobError::Cancelled`] if it was skipped because a task it depends on failed.
+    pub fn outcome(&self, id: TaskId) -> &Result<(), JobError> {
+        &self.outcomes[id.0]
+    }
+
+    /// Returns `true` if every task ran to completion.
+    pub fn succeeded(&self) -> bool {
+        self.outcomes.iter().all(Result::is_ok)
+    }
+
+    /// Tasks that panicked.
+    pub fn panicked(&self) -> Vec<TaskId> {
+        self.matching(|outcome| matches!(outcome, Err(JobError::Panicked(_))))
+    }
+
+    /// Tasks that never ran because something upstream of them failed.
+    pub fn skipped(&self) -> Vec<TaskId> {
+        self.matching(|outcome| matches!(outcome, Err(JobError::Cancelled)))
+    }
+
+    fn matching(&self, pred: impl Fn(&Result<(), JobError>) -> bool) -> Vec<TaskId> {
+        (0..self.outcomes.len())
+            .filter(|&i| pred(&self.outcomes[i]))
+            .map(TaskId)
+            .collect()
+    }
+}
+
+/// A graph while it runs, shared by the jobs running its tasks.
+struct GraphState {
+    jobs: Vec<Mutex<Option<Job>>>,
+    successors: Vec<Vec<usize>>,
+    /// Predecessors each task is still waiting for, whoever takes it to zero starts the task.
+    waiting: Vec<AtomicUsize>,
+    /// Set once any predecessor fails, so the task is skipped instead of started.
+    upstream_failed: Vec<AtomicBool>,
+    outcomes: Mutex<Vec<Option<Result<(), JobError>>>>,
+    unfinished: Latch,
+    pool: Arc<Shared>,
+}
+
+impl GraphState {
+    fn start(self: &Arc<Self>, id: usize) {
+        let job = GraphJob(Some((Arc::clone(self), id)));
+        self.pool.execute(move || job.run());
+    }
+
+    /// Records the outcome of `id`, then starts or skips the successors it was the last one
+    /// holding up.
+    fn finish(self: &Arc<Self>, id: usize, outcome: Result<(), JobError>) {
+        // A worklist rather than recursion, skipping a long chain shouldn't grow the stack
+        let mut finished = vec![(id, outcome)];
+
+        while let Some((id, outcome)) = finished.pop() {
+            let failed = outcome.is_err();
+            lock(&self.outcomes)[id] = Some(outcome);
+
+            for &next in &self.successors[id] {
+                if failed {
+                    self.upstream_failed[next].store(true, Ordering::SeqCst);
+                }
+
+                if self.waiting[next].fetch_sub(1, Ordering::SeqCst) != 1 {
+                    continue;
+                }
+
+                if self.upstream_failed[next].load(Ordering::SeqCst) {
+                    lock(&self.jobs[next]).take();
+                    finished.push((next, Err(JobError::Cancelled)));
+                } else {
+                    self.start(next);
+                }
+            }
+
+            self.unfinished.done();
+        }
+    }
+}
+
+/// The job queued for one task. If the queue drops it without running it, the task counts as
+/// failed so its successors are skipped and the run still finishes.
+struct GraphJob(Option<(Arc<GraphState>, usize)>);
+
+impl GraphJob {
+    fn run(mut self) {
+        if let Some((state, id)) = self.0.take() {
+            let outcome = match lock(&state.jobs[id]).take() {
+                Some(job) => panic::catch_unwind(AssertUnwindSafe(job)).map_err(JobError::Panicked),
+                None => Err(JobError::Cancelled),
+            };
+
+            state.finish(id, outcome);
+        }
+    }
+}
+
+impl Drop for GraphJob {
+    fn drop(&mut self) {
+        if let Some((state, id)) = self.0.take() {
+            lock(&state.jobs[id]).take();
+            state.finish(id, Err(JobError::Cancelled));
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Runs every task in `graph` on the pool, each one once all the tasks it depends on have
+    /// finished, and blocks until they're all done or skipped.
+    ///
+    /// Returns [`GraphCycle`] without running anything if the dependencies form a cycle.
+    /// Calling this from a job on the same pool ties up that worker for the whole run.
+    pub fn run_graph(&self, graph: TaskGraph) -> Result<GraphReport, GraphCycle> {
+        let predecessors = graph.predecessors();
+        graph.check_acyclic(&predecessors)?;
+
+        // Found before anything starts, once tasks finish other counts start reaching zero too
+        let roots: Vec<usize> = (0..predecessors.len())
+            .filter(|&i| predecessors[i] == 0)
+            .collect();
+
+        let len = graph.tasks.len();
+        let state = Arc::new(GraphState {
+            jobs: graph
+                .tasks
+                .into_iter()
+                .map(|job| Mutex::new(Some(job)))
+                .collect(),
+            successors: graph.successors,
+            waiting: predecessors.into_iter().map(AtomicUsize::new).collect(),
+            upstream_failed: (0..len).map(|_| AtomicBool::new(false)).collect(),
+            outcomes: Mutex::new((0..len).map(|_| None).collect()),
+            unfinished: Latch::new(),
+            pool: Arc::clone(&self.shared),
+        });
+
+        for _ in 0..len {
+            state.unfinished.add();
+        }
+        for id in roots {
+            state.start(id);
+        }
+
+        state.unfinished.wait();
+
+        let outcomes = lock(&state.outcomes)
+            .drain(..)
+            .map(|outcome| outcome.expect("every task finishes before the latch opens"))
+            .collect();
+
+        Ok(GraphReport { outcomes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recording(
+        log: &Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+    ) -> impl FnOnce() + Send + use<> {
+        let log = Arc::clone(log);
+        move || lock(&log).push(name)
+    }
+
+    #[test]
+    fn tasks_run_after_their_dependencies() {
+        let pool = ThreadPool::new(3);
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut graph = TaskGraph::new();
+        let a = graph.add_task(recording(&log, "a"));
+        let b = graph.add_task(recording(&log, "b"));
+        let c = graph.add_task(recording(&log, "c"));
+        let d = graph.add_task(recording(&log, "d"));
+        graph.add_dependency(a, b);
+        graph.add_dependency(a, c);
+        graph.add_dependency(b, d);
+        graph.add_dependency(c, d);
+
+        let report = pool.run_graph(graph).unwrap();
+        assert!(report.succeeded());
+
+        let log = lock(&log);
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[0], "a");
+        assert_eq!(log[3], "d");
+    }
+
+    #[test]
+    fn failures_skip_everything_downstream() {
+        let pool = ThreadPool::new(2);
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut graph = TaskGraph::new();
+        let fails = graph.add_task(|| panic!("build failed"));
+        let after = graph.add_task(recording(&log, "after"));
+        let later = graph.add_task(recording(&log, "later"));
+        let other = graph.add_task(recording(&log, "other"));
+        let joined = graph.add_task(recording(&log, "joined"));
+        graph.add_dependency(fails, after);
+        graph.add_dependency(after, later);
+        graph.add_dependency(other, joined);
+        graph.add_dependency(later, joined);
+
+        let report = pool.run_graph(graph).unwrap();
+
+        assert!(!report.succeeded());
+        assert_eq!(report.panicked(), [fails]);
+        assert_eq!(report.skipped(), [after, later, joined]);
+        assert!(report.outcome(other).is_ok());
+        assert_eq!(*lock(&log), ["other"]);
+    }
+
+    #[test]
+    fn cycles_are_rejected_before_anything_runs() {
+        let pool = ThreadPool::new(2);
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mut graph = TaskGraph::new();
+        let first = graph.add_task(recording(&log, "first"));
+        let a = graph.add_task(recording(&log, "a"));
+        let b = graph.add_task(recording(&log, "b"));
+        graph.add_dependency(first, a);
+        graph.add_dependency(a, b);
+        graph.add_dependency(b, a);
+
+        let err = pool.run_graph(graph).unwrap_err();
+
+        assert_eq!(err.tasks(), [a, b]);
+        assert!(lock(&log).is_empty());
+    }
+}
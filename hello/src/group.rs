@@ -0,0 +1,206 @@
+use std::{
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        Arc, Condvar, Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{ThreadPool, lock};
+
+/// Counter that threads can wait on until it drops back to zero.
+///
+/// Counting is lock-free, the lock is only taken by waiters and by whoever brings the count to
+/// zero.
+pub(crate) struct Latch {
+    count: AtomicUsize,
+    lock: Mutex<()>,
+    zero: Condvar,
+}
+
+impl Latch {
+    pub(crate) fn new() -> Latch {
+        Latch {
+            count: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            zero: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn add(&self) {
+        self.count.fetch_add(1, Ordering::SeqCst);
+    }
+
+    pub(crate) fn done(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 {
+            // Waiters check the count while holding the lock, taking it here means none of them
+            // can be between that check and `wait`
+            let _guard = lock(&self.lock);
+            self.zero.notify_all();
+        }
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn wait(&self) {
+        let mut guard = lock(&self.lock);
+
+        while self.count() > 0 {
+            guard = self
+                .zero
+                .wait(guard)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    /// Returns `false` if the count was still above zero after `timeout`.
+    pub(crate) fn wait_timeout(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        let mut guard = lock(&self.lock);
+
+        while self.count() > 0 {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+
+            guard = self
+                .zero
+                .wait_timeout(guard, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+
+        true
+    }
+}
+
+/// Batch of jobs that can be waited on without shutting the pool down, created by
+/// [`ThreadPool::group`].
+pub struct JobGroup<'pool> {
+    pool: &'pool ThreadPool,
+    state: Arc<GroupState>,
+}
+
+struct GroupState {
+    pending: Latch,
+    panicked: AtomicUsize,
+}
+
+/// Marks a group job as done when dropped, whether it ran or the queue turned it away.
+struct Pending(Arc<GroupState>);
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.0.pending.done();
+    }
+}
+
+impl JobGroup<'_> {
+    /// Queues `f` on the pool as part of this group.
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.state.pending.add();
+
+        let pending = Pending(Arc::clone(&self.state));
+
+        self.pool.execute(move || {
+            if panic::catch_unwind(AssertUnwindSafe(f)).is_err() {
+                pending.0.panicked.fetch_add(1, Ordering::Relaxed);
+            }
+
+            drop(pending);
+        });
+    }
+
+    /// Blocks until every job queued through this group so far has finished.
+    ///
+    /// The group can keep being used afterwards, a later `wait` covers the jobs queued since.
+    pub fn wait(&self) {
+        self.state.pending.wait();
+    }
+
+    /// Like [`JobGroup::wait`], but gives up after `timeout`, returns `false` if some jobs were
+    /// still unfinished.
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        self.state.pending.wait_timeout(timeout)
+    }
+
+    /// Number of jobs in the group that haven't finished.
+    pub fn pending(&self) -> usize {
+        self.state.pending.count()
+    }
+
+    /// Number of jobs in the group that panicked.
+    pub fn panicked(&self) -> usize {
+        self.state.panicked.load(Ordering::Relaxed)
+    }
+}
+
+impl ThreadPool {
+    /// Returns an empty [`JobGroup`] for queueing a batch of jobs on this pool and waiting for
+    /// just that batch.
+    pub fn group(&self) -> JobGroup<'_> {
+        JobGroup {
+            pool: self,
+            state: Arc::new(GroupState {
+                pending: Latch::new(),
+                panicked: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Blocks until the queue is empty and no worker is running a job.
+    ///
+    /// Jobs queued while waiting are waited for too. Jobs scheduled with the `execute_after`
+    /// family count once they come due. Calling this from a job on the same pool never returns,
+    /// since that job is still running.
+    pub fn wait_idle(&self) {
+        self.shared.outstanding.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn groups_and_wait_idle_leave_the_pool_running() {
+        let pool = ThreadPool::build(2).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+
+        for batch in 1..=2 {
+            let group = pool.group();
+            for _ in 0..10 {
+                let count = Arc::clone(&count);
+                group.execute(move || {
+                    thread::sleep(Duration::from_millis(1));
+                    count.fetch_add(1, Ordering::SeqCst);
+                });
+            }
+            group.execute(|| panic!("counted by the group"));
+
+            group.wait();
+            assert_eq!(group.pending(), 0);
+            assert_eq!(group.panicked(), 1);
+            assert_eq!(count.load(Ordering::SeqCst), batch * 10);
+        }
+
+        for _ in 0..10 {
+            let count = Arc::clone(&count);
+            pool.execute(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+
+        pool.wait_idle();
+        assert_eq!(count.load(Ordering::SeqCst), 30);
+        assert_eq!(pool.stats().active_workers, 0);
+    }
+}
@@ -0,0 +1,179 @@
+use std::{
+    any::Any,
+    cell::Cell,
+    error::Error,
+    fmt,
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+/// Handle to a job started with [`ThreadPool::spawn`](crate::ThreadPool::spawn) or
+/// [`ThreadPool::spawn_future`](crate::ThreadPool::spawn_future).
+///
+/// The handle receives the value the job returns, or the panic payload if the job panicked.
+pub struct JobHandle<T> {
+    receiver: mpsc::Receiver<thread::Result<T>>,
+    /// Whether the result has been handed out, the job may not have dropped its sender yet.
+    spent: Cell<bool>,
+}
+
+/// Reason a [`JobHandle`] didn't produce a value.
+pub enum JobError {
+    /// The job panicked, holds the payload passed to `panic!`.
+    Panicked(Box<dyn Any + Send + 'static>),
+    /// The job was dropped before it finished, for example because the pool shut down first.
+    Cancelled,
+    /// The job's result was already returned by [`JobHandle::try_join`] or
+    /// [`JobHandle::join_timeout`].
+    AlreadyJoined,
+}
+
+impl JobError {
+    /// Returns `true` if the job panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self, JobError::Panicked(_))
+    }
+}
+
+impl fmt::Debug for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Panicked(_) => f.write_str("Panicked(..)"),
+            JobError::Cancelled => f.write_str("Cancelled"),
+            JobError::AlreadyJoined => f.write_str("AlreadyJoined"),
+        }
+    }
+}
+
+impl fmt::Display for JobError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobError::Panicked(payload) => match panic_message(payload.as_ref()) {
+                Some(msg) => write!(f, "job panicked: {msg}"),
+                None => f.write_str("job panicked"),
+            },
+            JobError::Cancelled => f.write_str("job was dropped before it ran"),
+            JobError::AlreadyJoined => f.write_str("job result was already taken"),
+        }
+    }
+}
+
+impl Error for JobError {}
+
+/// Extracts the message from a panic payload when it's a string, which covers `panic!` with a
+/// literal or a format string.
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        Some(s)
+    } else {
+        payload.downcast_ref::<String>().map(String::as_str)
+    }
+}
+
+impl<T> JobHandle<T> {
+    pub(crate) fn new(receiver: mpsc::Receiver<thread::Result<T>>) -> JobHandle<T> {
+        JobHandle {
+            receiver,
+            spent: Cell::new(false),
+        }
+    }
+
+    /// Blocks until the job finishes and returns its value, or [`JobError::AlreadyJoined`] if
+    /// the value was already taken.
+    pub fn join(self) -> Result<T, JobError> {
+        if self.spent.get() {
+            return Err(JobError::AlreadyJoined);
+        }
+
+        match self.receiver.recv() {
+            Ok(result) => result.map_err(JobError::Panicked),
+            Err(_) => Err(JobError::Cancelled),
+        }
+    }
+
+    /// Returns the job's result if it has already finished, without blocking.
+    ///
+    /// Returns `None` while the job is queued or running. Once a result has been returned the
+    /// handle is spent and later calls report [`JobError::AlreadyJoined`].
+    pub fn try_join(&self) -> Option<Result<T, JobError>> {
+        if self.spent.get() {
+            return Some(Err(JobError::AlreadyJoined));
+        }
+
+        match self.receiver.try_recv() {
+            Ok(result) => Some(self.spend(result)),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(JobError::Cancelled)),
+        }
+    }
+
+    /// Waits up to `timeout` for the job to finish, returns `None` if it's still running.
+    ///
+    /// Like [`JobHandle::try_join`], the handle is spent once a result has been returned.
+    pub fn join_timeout(&self, timeout: Duration) -> Option<Result<T, JobError>> {
+        if self.spent.get() {
+            return Some(Err(JobError::AlreadyJoined));
+        }
+
+        match self.receiver.recv_timeout(timeout) {
+            Ok(result) => Some(self.spend(result)),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => Some(Err(JobError::Cancelled)),
+        }
+    }
+
+    fn spend(&self, result: thread::Result<T>) -> Result<T, JobError> {
+        self.spent.set(true);
+
+        result.map_err(JobError::Panicked)
+    }
+}
+
+impl<T> fmt::Debug for JobHandle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JobHandle").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ThreadPool, tests::parked};
+
+    #[test]
+    fn try_join_and_join_timeout_poll_without_consuming_the_handle() {
+        let pool = ThreadPool::build(1).unwrap();
+        let release_tx = parked(&pool);
+
+        let handle = pool.spawn(|| 7);
+
+        // Pending
+        assert!(handle.try_join().is_none());
+        assert!(handle.join_timeout(Duration::from_millis(10)).is_none());
+
+        // Finished
+        release_tx.send(()).unwrap();
+        let finished = handle.join_timeout(Duration::from_secs(5));
+        assert_eq!(finished.unwrap().unwrap(), 7);
+
+        // Spent
+        assert!(matches!(
+            handle.try_join(),
+            Some(Err(JobError::AlreadyJoined))
+        ));
+        assert!(matches!(
+            handle.join_timeout(Duration::from_millis(10)),
+            Some(Err(JobError::AlreadyJoined))
+        ));
+
+        let handle = pool.spawn(|| 8);
+        pool.wait_idle();
+        assert_eq!(handle.try_join().unwrap().unwrap(), 8);
+        assert!(matches!(
+            handle.try_join(),
+            Some(Err(JobError::AlreadyJoined))
+        ));
+        assert!(matches!(handle.join(), Err(JobError::AlreadyJoined)));
+    }
+}
@@ -0,0 +1,33 @@
+//! The HTTP/1.1 pieces the `hello` server is built from.
+
+mod connection;
+mod files;
+mod headers;
+mod request;
+mod response;
+mod router;
+
+pub use connection::{KeepAlive, serve_connection};
+pub use files::{StaticFiles, content_type};
+pub use headers::Headers;
+pub use request::{DEFAULT_MAX_BODY, ParseError, Request, Version};
+pub use response::{Body, Response};
+pub use router::{Params, Router};
+
+/// Standard reason phrase for `status`, or `"Unknown"` for codes the server never sends.
+pub fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Content Too Large",
+        431 => "Request Header Fields Too Large",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::{self, BufRead, BufReader, Read},
+    net::{Shutdown, TcpStream},
+    time::Duration,
+};
+
+use super::{ParseError, Request, Response, Router, Version, reason_phrase};
+
+/// Longest a closing connection waits for the client to stop sending.
+const LINGER: Duration = Duration::from_secs(1);
+/// Most bytes read and discarded while closing.
+const LINGER_BYTES: u64 = 64 * 1024;
+
+/// Limits on how long a connection is kept open between requests, see [`serve_connection`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepAlive {
+    /// How long to wait for the next request, or for the rest of one that has started, before
+    /// closing the connection. 5 seconds by default.
+    pub idle_timeout: Duration,
+    /// Most requests answered on one connection, the last response says `Connection: close`.
+    /// 100 by default.
+    pub max_requests: usize,
+}
+
+impl Default for KeepAlive {
+    fn default() -> KeepAlive {
+        KeepAlive {
+            idle_timeout: Duration::from_secs(5),
+            max_requests: 100,
+        }
+    }
+}
+
+/// Answers requests on `stream` with `router` until the connection should close.
+///
+/// HTTP/1.1 connections stay open unless the client or a handler sends `Connection: close`,
+/// HTTP/1.0 ones only if the client asks with `Connection: keep-alive`. Requests sent back to
+/// back without waiting for responses are answered in the order they arrived.
+///
+/// The connection is closed once [`KeepAlive::max_requests`] have been answered, when no
+/// request arrives within [`KeepAlive::idle_timeout`], and after a malformed request, which
+/// gets an error response first. A worker running this stays busy for the whole connection.
+///
+/// Returns an error only if reading or writing the socket fails for a reason other than the
+/// client going away or timing out.
+pub fn serve_connection(
+    stream: &TcpStream,
+    router: &Router,
+    keep_alive: &KeepAlive,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(keep_alive.idle_timeout))?;
+
+    let mut reader = BufReader::new(stream);
+    let mut writer = stream;
+
+    for served in 1.. {
+        // Waiting for the first byte tells a client that's done apart from a broken request
+        match reader.fill_buf() {
+            Ok([]) => return Ok(()),
+            Ok(_) => {}
+            Err(err) if is_disconnect(&err) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+
+        let request = match Request::read_from(&mut reader) {
+            Ok(request) => request,
+            Err(ParseError::Io(err)) if is_disconnect(&err) => return Ok(()),
+            Err(ParseError::Io(err)) => return Err(err),
+            Err(err) => {
+                let status = err.status();
+                let reason = reason_phrase(status);
+
+                let response = Response::text(status, format!("{status} {reason}: {err}\n"))
+                    .with_header("Connection", "close");
+                ignore_disconnect(response.write_to(&mut writer))?;
+                break;
+            }
+        };
+
+        let mut response = router.handle(&request);
+
+        // An HTTP/1.0 client can only tell where a chunked body ends by the connection closing
+        let keep_open = served < keep_alive.max_requests
+            && wants_keep_alive(&request)
+            && !has_token(response.headers().get("Connection"), "close")
+            && !(request.version() == Version::Http10 && response.is_chunked());
+
+        response
+            .headers_mut()
+            .set("Connection", if keep_open { "keep-alive" } else { "close" });
+
+        ignore_disconnect(response.write_for(request.version(), &mut writer))?;
+
+        if !keep_open {
+            break;
+        }
+    }
+
+    linger(stream, &mut reader);
+    Ok(())
+}
+
+/// Closes our side and reads whatever the client still sends, for up to [`LINGER`].
+///
+/// Closing a socket with unread data in it resets the connection, which can throw away a
+/// response the client hasn't read yet, for example when it pipelined more requests than it
+/// was allowed.
+fn linger(stream: &TcpStream, reader: &mut BufReader<&TcpStream>) {
+    if stream.shutdown(Shutdown::Write).is_err() || stream.set_read_timeout(Some(LINGER)).is_err() {
+        return;
+    }
+
+    let _ = io::copy(&mut reader.take(LINGER_BYTES), &mut io::sink());
+}
+
+/// Whether the client is willing to send another request on this connection.
+fn wants_keep_alive(request: &Request) -> bool {
+    let connection = request.header("Connection");
+
+    match request.version() {
+        Version::Http11 => !has_token(connection, "close"),
+        Version::Http10 => has_token(connection, "keep-alive"),
+    }
+}
+
+/// Whether a comma-separated header value like `Connection` lists `token`.
+fn has_token(value: Option<&str>, token: &str) -> bool {
+    value.is_some_and(|value| {
+        value
+            .split(',')
+            .any(|item| item.trim().eq_ignore_ascii_case(token))
+    })
+}
+
+/// Errors that mean the client went away or went quiet, rather than something going wrong.
+fn is_disconnect(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+fn ignore_disconnect(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if !is_disconnect(&err) => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{io::Write, net::TcpListener, thread, time::Instant};
+
+    /// Serves one connection on a fresh port, returns the client end.
+    fn connect(keep_alive: KeepAlive) -> (TcpStream, thread::JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let router = Router::new().get("/*path", |request, _| {
+                Response::text(200, format!("[{}]", request.path()))
+            });
+            let (stream, _) = listener.accept()?;
+
+            serve_connection(&stream, &router, &keep_alive)
+        });
+
+        (TcpStream::connect(addr).unwrap(), server)
+    }
+
+    /// Bodies of the responses read until the server closes, in order.
+    fn bodies(client: &mut TcpStream) -> Vec<String> {
+        let mut raw = String::new();
+        client.read_to_string(&mut raw).unwrap();
+
+        raw.split('[')
+            .skip(1)
+            .map(|rest| rest.split(']').next().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order() {
+        let (mut client, server) = connect(KeepAlive::default());
+
+        client
+            .write_all(
+                b"GET /one HTTP/1.1\r\n\r\n\
+                  GET /two HTTP/1.1\r\nConnection: keep-alive\r\n\r\n\
+                  GET /three HTTP/1.1\r\nConnection: close\r\n\r\n\
+                  GET /never HTTP/1.1\r\n\r\n",
+            )
+            .unwrap();
+
+        assert_eq!(bodies(&mut client), ["/one", "/two", "/three"]);
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn max_requests_and_http10_close_the_connection() {
+        let (mut client, server) = connect(KeepAlive {
+            max_requests: 2,
+            ..KeepAlive::default()
+        });
+        client
+            .write_all(b"GET /a HTTP/1.1\r\n\r\nGET /b HTTP/1.1\r\n\r\nGET /c HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        assert_eq!(bodies(&mut client), ["/a", "/b"]);
+        drop(client);
+        server.join().unwrap().unwrap();
+
+        let (mut client, server) = connect(KeepAlive::default());
+        client
+            .write_all(b"GET /old HTTP/1.0\r\n\r\nGET /more HTTP/1.0\r\n\r\n")
+            .unwrap();
+
+        assert_eq!(bodies(&mut client), ["/old"]);
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn idle_connections_time_out() {
+        let (mut client, server) = connect(KeepAlive {
+            idle_timeout: Duration::from_millis(100),
+            ..KeepAlive::default()
+        });
+        let start = Instant::now();
+
+        client.write_all(b"GET /x HTTP/1.1\r\n\r\n").unwrap();
+
+        assert_eq!(bodies(&mut client), ["/x"]);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        drop(client);
+        server.join().unwrap().unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fs::File,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use super::{Body, Response};
+
+/// Serves files from a document root, see [`StaticFiles::serve`].
+#[derive(Debug, Clone)]
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+impl StaticFiles {
+    /// Serves files under `root`.
+    pub fn new(root: impl Into<PathBuf>) -> StaticFiles {
+        StaticFiles { root: root.into() }
+    }
+
+    /// Directory files are served from.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Responds with the file at `path` under the root, streamed from disk with a
+    /// `Content-Type` guessed from its extension. A directory is served its `index.html`.
+    ///
+    /// `path` is the decoded request path, or a capture from it, with or without a leading
+    /// `/`. Paths with `..` or `.` segments, backslashes, or NUL bytes are answered with 403
+    /// before the filesystem is touched, as are files that resolve outside the root through a
+    /// symlink. Missing files are a 404.
+    pub fn serve(&self, path: &str) -> Response {
+        let Some(relative) = sanitize(path) else {
+            return Response::text(403, "403 Forbidden\n");
+        };
+
+        match self.open(&relative) {
+            Ok(response) => response,
+            Err(err) => match err.kind() {
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory => {
+                    Response::text(404, "404 Not Found\n")
+                }
+                io::ErrorKind::PermissionDenied => Response::text(403, "403 Forbidden\n"),
+                _ => Response::text(500, "500 Internal Server Error\n"),
+            },
+        }
+    }
+
+    fn open(&self, relative: &Path) -> io::Result<Response> {
+        let root = self.root.canonicalize()?;
+        let mut path = root.join(relative);
+        if path.is_dir() {
+            path.push("index.html");
+        }
+
+        // Checked on the file that gets opened, since a directory's index can be a symlink too
+        let path = path.canonicalize()?;
+        if !path.starts_with(&root) {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        if !metadata.is_file() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        Ok(Response::new(200)
+            .with_header("Content-Type", content_type(&path))
+            .with_body(Body::Stream {
+                reader: Box::new(file),
+                length: metadata.len(),
+            }))
+    }
+}
+
+/// Turns a request path into a relative path with only plain segments, `None` if it has
+/// anything that could step outside the root.
+fn sanitize(path: &str) -> Option<PathBuf> {
+    if path.contains(['\\', '\0']) {
+        return None;
+    }
+
+    let mut relative = PathBuf::new();
+
+    for segment in path.split('/').filter(|segment| !segment.is_empty()) {
+        // Checking the segments as `Path` sees them too catches anything platform specific,
+        // like a drive prefix
+        let mut components = Path::new(segment).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(name)), None) if segment != "." && segment != ".." => {
+                relative.push(name);
+            }
+            _ => return None,
+        }
+    }
+
+    Some(relative)
+}
+
+/// `Content-Type` for a file, by its extension.
+pub fn content_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    match extension.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("json") => "application/json",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("xml") => "application/xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("webp") => "image/webp",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        Some("pdf") => "application/pdf",
+        Some("wasm") => "application/wasm",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::Request;
+    use std::{env, fs, process};
+
+    /// Fresh document root with an index, a stylesheet and a binary file, plus a secret next to
+    /// it that must stay out of reach.
+    fn site(name: &str) -> (PathBuf, StaticFiles) {
+        let dir = env::temp_dir().join(format!("hello-static-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        fs::create_dir_all(dir.join("public/docs")).unwrap();
+        fs::write(dir.join("public/index.html"), "<h1>home</h1>").unwrap();
+        fs::write(dir.join("public/docs/index.html"), "<h1>docs</h1>").unwrap();
+        fs::write(dir.join("public/site.CSS"), "body {}").unwrap();
+        fs::write(
+            dir.join("public/logo.png"),
+            [0x89, b'P', b'N', b'G', 0, 0xff],
+        )
+        .unwrap();
+        fs::write(dir.join("secret.txt"), "hunter2").unwrap();
+
+        let files = StaticFiles::new(dir.join("public"));
+        (dir, files)
+    }
+
+    fn written(response: Response) -> Vec<u8> {
+        let mut out = Vec::new();
+        response.write_to(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn serves_files_with_content_types_and_directory_indexes() {
+        let (dir, files) = site("serve");
+
+        let cases = [
+            ("/", "text/html; charset=utf-8", &b"<h1>home</h1>"[..]),
+            ("docs", "text/html; charset=utf-8", b"<h1>docs</h1>"),
+            ("/site.CSS", "text/css; charset=utf-8", b"body {}"),
+            ("logo.png", "image/png", &[0x89, b'P', b'N', b'G', 0, 0xff]),
+        ];
+        for (path, content_type, contents) in cases {
+            let response = files.serve(path);
+            assert_eq!(response.status(), 200, "{path}");
+            assert_eq!(response.headers().get("content-type"), Some(content_type));
+
+            let bytes = written(response);
+            assert!(bytes.ends_with(contents), "{path}");
+        }
+
+        assert_eq!(files.serve("missing.js").status(), 404);
+        assert_eq!(files.serve("logo.png/x").status(), 404);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_traversal_including_encoded_forms() {
+        let (dir, files) = site("traversal");
+
+        for path in [
+            "../secret.txt",
+            "docs/../../secret.txt",
+            "./index.html",
+            "..\\secret.txt",
+        ] {
+            assert_eq!(files.serve(path).status(), 403, "{path}");
+        }
+
+        // Symlinks out of the root, named directly or standing in for a directory's index
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::symlink;
+
+            fs::create_dir(dir.join("public/linked")).unwrap();
+            symlink("../secret.txt", dir.join("public/leak.txt")).unwrap();
+            symlink("../../secret.txt", dir.join("public/linked/index.html")).unwrap();
+            for path in ["leak.txt", "linked", "linked/", "linked/index.html"] {
+                assert_eq!(files.serve(path).status(), 403, "{path}");
+            }
+        }
+
+        // Escapes are decoded when the request is parsed, so they arrive here as plain `..`
+        for target in [
+            "/%2e%2e/secret.txt",
+            "/docs/%2E%2E%2F%2e%2e%2fsecret.txt",
+            "/..%5csecret.txt",
+        ] {
+            let raw = format!("GET {target} HTTP/1.1\r\n\r\n");
+            let request = Request::read_from(&mut raw.as_bytes()).unwrap();
+
+            assert_eq!(files.serve(request.path()).status(), 403, "{target}");
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,529 @@
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, Read},
+};
+
+use super::Headers;
+
+/// Longest request line or header line accepted, in bytes.
+const MAX_LINE: usize = 8 * 1024;
+/// Most header fields accepted in one request.
+const MAX_HEADERS: usize = 100;
+/// Largest body [`Request::read_from`] accepts, in bytes.
+pub const DEFAULT_MAX_BODY: usize = 1024 * 1024;
+
+/// HTTP version a request was sent with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http10,
+    Http11,
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Version::Http10 => f.write_str("HTTP/1.0"),
+            Version::Http11 => f.write_str("HTTP/1.1"),
+        }
+    }
+}
+
+/// An HTTP/1.x request read from a connection.
+#[derive(Debug, Clone)]
+pub struct Request {
+    method: String,
+    target: String,
+    path: String,
+    query: Vec<(String, String)>,
+    version: Version,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl Request {
+    /// Reads one request from `reader`, leaving anything after its body unread.
+    ///
+    /// The body is read according to `Content-Length`, or decoded from chunks if it's sent with
+    /// `Transfer-Encoding: chunked`, a request with neither has an empty body. Bodies over
+    /// [`DEFAULT_MAX_BODY`] bytes are rejected.
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Request, ParseError> {
+        Request::read_from_limited(reader, DEFAULT_MAX_BODY)
+    }
+
+    /// Reads one request like [`Request::read_from`], rejecting bodies over `max_body` bytes
+    /// with [`ParseError::BodyTooLarge`].
+    pub fn read_from_limited(
+        reader: &mut impl BufRead,
+        max_body: usize,
+    ) -> Result<Request, ParseError> {
+        let line = read_line(reader)?.ok_or(ParseError::MissingRequestLine)?;
+        if line.is_empty() {
+            return Err(ParseError::MissingRequestLine);
+        }
+
+        let mut parts = line.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(ParseError::MalformedRequestLine);
+        };
+
+        if method.is_empty() || !method.bytes().all(is_token_byte) || !target.starts_with('/') {
+            return Err(ParseError::MalformedRequestLine);
+        }
+
+        let version = match version {
+            "HTTP/1.1" => Version::Http11,
+            "HTTP/1.0" => Version::Http10,
+            v if v.starts_with("HTTP/") => return Err(ParseError::UnsupportedVersion),
+            _ => return Err(ParseError::MalformedRequestLine),
+        };
+
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target, ""),
+        };
+
+        let headers = read_headers(reader)?;
+        let body = read_body(reader, &headers, max_body)?;
+
+        Ok(Request {
+            method: method.to_string(),
+            path: percent_decode(path, false)?,
+            query: parse_query(query)?,
+            target: target.to_string(),
+            version,
+            headers,
+            body,
+        })
+    }
+
+    /// Request method, such as `GET`, exactly as sent.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Request target as sent, e.g. `/search?q=rust%20book`.
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// Path part of the target with percent-escapes decoded, e.g. `/search`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Value of the first query parameter called `name`, decoded.
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every query parameter in order, decoded, a parameter without `=` has an empty value.
+    pub fn query_pairs(&self) -> &[(String, String)] {
+        &self.query
+    }
+
+    /// HTTP version the request was sent with.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Header fields, looked up without regard to case.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Value of the first header called `name`.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)
+    }
+
+    /// Request body, empty if the request didn't have one.
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+}
+
+/// Reason a request couldn't be read.
+#[derive(Debug)]
+pub enum ParseError {
+    /// Reading from the connection failed.
+    Io(io::Error),
+    /// The connection ended, or sent a blank line, where the request line should be.
+    MissingRequestLine,
+    /// The request line isn't `METHOD /target HTTP/x.y`.
+    MalformedRequestLine,
+    /// The request asked for an HTTP version other than 1.0 or 1.1.
+    UnsupportedVersion,
+    /// A header line has no `:`, an invalid name, or is a folded continuation line.
+    MalformedHeader,
+    /// A line was longer than the server accepts, or there were too many headers.
+    HeadersTooLarge,
+    /// `Content-Length` isn't a number, appears more than once with different values, or is
+    /// sent along with `Transfer-Encoding`.
+    InvalidContentLength,
+    /// A chunk of a chunked body has a bad size line or isn't followed by a line break.
+    InvalidChunk,
+    /// The body is bigger than the server accepts.
+    BodyTooLarge,
+    /// The body is encoded with a `Transfer-Encoding` the server doesn't support.
+    UnsupportedTransferEncoding,
+    /// The connection ended partway through the request.
+    UnexpectedEof,
+    /// The path or query has a bad percent-escape, or decodes to invalid UTF-8.
+    InvalidEncoding,
+}
+
+impl ParseError {
+    /// Status code of the response the client should get for this error.
+    pub fn status(&self) -> u16 {
+        match self {
+            ParseError::UnsupportedVersion => 505,
+            ParseError::HeadersTooLarge => 431,
+            ParseError::BodyTooLarge => 413,
+            ParseError::UnsupportedTransferEncoding => 501,
+            _ => 400,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Io(err) => write!(f, "failed to read request: {err}"),
+            ParseError::MissingRequestLine => f.write_str("missing request line"),
+            ParseError::MalformedRequestLine => f.write_str("malformed request line"),
+            ParseError::UnsupportedVersion => f.write_str("unsupported HTTP version"),
+            ParseError::MalformedHeader => f.write_str("malformed header line"),
+            ParseError::HeadersTooLarge => f.write_str("request headers too large"),
+            ParseError::InvalidContentLength => f.write_str("invalid Content-Length"),
+            ParseError::InvalidChunk => f.write_str("malformed chunk in chunked body"),
+            ParseError::BodyTooLarge => f.write_str("request body too large"),
+            ParseError::UnsupportedTransferEncoding => f.write_str("unsupported Transfer-Encoding"),
+            ParseError::UnexpectedEof => f.write_str("connection closed mid-request"),
+            ParseError::InvalidEncoding => f.write_str("invalid percent-encoding"),
+        }
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(err: io::Error) -> ParseError {
+        match err.kind() {
+            io::ErrorKind::UnexpectedEof => ParseError::UnexpectedEof,
+            _ => ParseError::Io(err),
+        }
+    }
+}
+
+/// Reads a line ending in CRLF or a bare LF and strips the ending, `None` at end of input.
+fn read_line(reader: &mut impl BufRead) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    let read = reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 2)
+        .read_until(b'\n', &mut line)?;
+
+    if read == 0 {
+        return Ok(None);
+    }
+
+    if line.pop() != Some(b'\n') {
+        // Either the limit cut the line off or the input ended partway through it
+        return Err(if read > MAX_LINE {
+            ParseError::HeadersTooLarge
+        } else {
+            ParseError::UnexpectedEof
+        });
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::MalformedHeader)
+}
+
+fn read_headers(reader: &mut impl BufRead) -> Result<Headers, ParseError> {
+    let mut headers = Headers::new();
+
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::UnexpectedEof)?;
+        if line.is_empty() {
+            return Ok(headers);
+        }
+
+        if headers.len() == MAX_HEADERS {
+            return Err(ParseError::HeadersTooLarge);
+        }
+
+        let (name, value) = line.split_once(':').ok_or(ParseError::MalformedHeader)?;
+
+        // A name can't have whitespace in it, which also catches obsolete line folding
+        if name.is_empty() || !name.bytes().all(is_token_byte) {
+            return Err(ParseError::MalformedHeader);
+        }
+
+        headers.append(name, value.trim_matches([' ', '\t']));
+    }
+}
+
+fn read_body(
+    reader: &mut impl BufRead,
+    headers: &Headers,
+    max_body: usize,
+) -> Result<Vec<u8>, ParseError> {
+    if let Some(encoding) = headers.get("Transfer-Encoding") {
+        // Two ways of framing the body is how requests get smuggled past proxies
+        if headers.contains("Content-Length") {
+            return Err(ParseError::InvalidContentLength);
+        }
+
+        if headers.get_all("Transfer-Encoding").count() > 1
+            || !encoding.trim().eq_ignore_ascii_case("chunked")
+        {
+            return Err(ParseError::UnsupportedTransferEncoding);
+        }
+
+        return read_chunked(reader, max_body);
+    }
+
+    let mut lengths = headers.get_all("Content-Length");
+    let Some(first) = lengths.next() else {
+        return Ok(Vec::new());
+    };
+
+    if lengths.any(|other| other != first) || !first.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(ParseError::InvalidContentLength);
+    }
+
+    let length: usize = first
+        .parse()
+        .map_err(|_| ParseError::InvalidContentLength)?;
+    if length > max_body {
+        return Err(ParseError::BodyTooLarge);
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+
+    Ok(body)
+}
+
+/// Decodes a chunked body: chunks of `<hex size>[;extensions]` CRLF, the data, CRLF, ending
+/// with a zero-sized chunk and optional trailer fields, which are read and dropped.
+fn read_chunked(reader: &mut impl BufRead, max_body: usize) -> Result<Vec<u8>, ParseError> {
+    let mut body = Vec::new();
+
+    loop {
+        let line = read_line(reader)?.ok_or(ParseError::UnexpectedEof)?;
+        let size = line
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim_matches([' ', '\t']);
+
+        if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidChunk);
+        }
+
+        // Too many digits to fit is too big either way
+        let size = usize::from_str_radix(size, 16).map_err(|_| ParseError::BodyTooLarge)?;
+        if size == 0 {
+            read_headers(reader)?;
+            return Ok(body);
+        }
+
+        if size > max_body - body.len() {
+            return Err(ParseError::BodyTooLarge);
+        }
+
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+
+        if !read_line(reader)?
+            .ok_or(ParseError::UnexpectedEof)?
+            .is_empty()
+        {
+            return Err(ParseError::InvalidChunk);
+        }
+    }
+}
+
+fn parse_query(query: &str) -> Result<Vec<(String, String)>, ParseError> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(key, true)?, percent_decode(value, true)?))
+        })
+        .collect()
+}
+
+/// Decodes `%XX` escapes, and in query strings `+` as a space.
+pub(crate) fn percent_decode(input: &str, plus_as_space: bool) -> Result<String, ParseError> {
+    let mut bytes = input.bytes();
+    let mut decoded = Vec::with_capacity(input.len());
+
+    while let Some(b) = bytes.next() {
+        match b {
+            b'%' => {
+                let hex = [
+                    bytes.next().ok_or(ParseError::InvalidEncoding)?,
+                    bytes.next().ok_or(ParseError::InvalidEncoding)?,
+                ];
+                // Both bytes must be hex digits, `from_str_radix` also takes a sign as in `%+1`
+                if !hex.iter().all(u8::is_ascii_hexdigit) {
+                    return Err(ParseError::InvalidEncoding);
+                }
+                let hex = std::str::from_utf8(&hex).map_err(|_| ParseError::InvalidEncoding)?;
+
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| ParseError::InvalidEncoding)?);
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            b => decoded.push(b),
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| ParseError::InvalidEncoding)
+}
+
+/// Characters allowed in methods and header names, RFC 9110's `tchar`.
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Request, ParseError> {
+        Request::read_from(&mut raw.as_bytes())
+    }
+
+    #[test]
+    fn parses_method_path_query_headers_and_body() {
+        let mut input = "POST /search%20results?q=rust+book&lang=en%2Dgb&flag HTTP/1.1\r\n\
+                         Host: localhost\r\n\
+                         content-length: 5\r\n\
+                         X-Tag:  one \r\n\
+                         x-tag: two\r\n\
+                         \r\n\
+                         helloGET / HTTP/1.0\r\n\r\n"
+            .as_bytes();
+
+        let request = Request::read_from(&mut input).unwrap();
+
+        assert_eq!(request.method(), "POST");
+        assert_eq!(request.path(), "/search results");
+        assert_eq!(request.query("q"), Some("rust book"));
+        assert_eq!(request.query("lang"), Some("en-gb"));
+        assert_eq!(request.query("flag"), Some(""));
+        assert_eq!(request.version(), Version::Http11);
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(
+            request.headers().get_all("X-TAG").collect::<Vec<_>>(),
+            ["one", "two"]
+        );
+        assert_eq!(request.body(), b"hello");
+
+        // The next request on the connection is left for the next read
+        let next = Request::read_from(&mut input).unwrap();
+        assert_eq!((next.path(), next.version()), ("/", Version::Http10));
+    }
+
+    #[test]
+    fn malformed_requests_map_to_error_statuses() {
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        let cases = [
+            ("", 400),
+            ("\r\n\r\n", 400),
+            ("GET /\r\n\r\n", 400),
+            ("GET / HTTP/1.1 extra\r\n\r\n", 400),
+            ("GET nope HTTP/1.1\r\n\r\n", 400),
+            ("GET / HTTP/2.0\r\n\r\n", 505),
+            ("GET / HTTP/1.1\r\nno colon\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nX: a\r\n folded\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nHost: x\r\n", 400),
+            ("GET /%zz HTTP/1.1\r\n\r\n", 400),
+            ("GET /a%+1b HTTP/1.1\r\n\r\n", 400),
+            ("GET / HTTP/1.1\r\nContent-Length: -1\r\n\r\n", 400),
+            (
+                "GET / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\n",
+                400,
+            ),
+            ("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort", 400),
+            ("POST / HTTP/1.1\r\nContent-Length: 99999999\r\n\r\n", 413),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\nContent-Length: 3\r\n\r\n",
+                400,
+            ),
+            (
+                "POST / HTTP/1.1\r\nTransfer-Encoding: gzip, chunked\r\n\r\n",
+                501,
+            ),
+            ("POST / HTTP/1.1\r\nTransfer-Encoding: gzip\r\n\r\n", 501),
+            (&long_header, 431),
+        ];
+
+        for (raw, status) in cases {
+            let err = parse(raw).unwrap_err();
+            assert_eq!(err.status(), status, "{raw:?} gave {err}");
+        }
+    }
+
+    #[test]
+    fn decodes_chunked_bodies_within_the_limit() {
+        let mut input = "POST /upload HTTP/1.1\r\n\
+                         Transfer-Encoding: Chunked\r\n\
+                         \r\n\
+                         5;name=value\r\nhello\r\n\
+                         7\r\n, world\r\n\
+                         0\r\n\
+                         Checksum: abc\r\n\
+                         \r\n\
+                         GET /next HTTP/1.1\r\n\r\n"
+            .as_bytes();
+
+        let request = Request::read_from(&mut input).unwrap();
+        assert_eq!(request.body(), b"hello, world");
+        assert_eq!(Request::read_from(&mut input).unwrap().path(), "/next");
+
+        let chunked = |chunks: &str| {
+            let raw = format!("POST / HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n{chunks}");
+            Request::read_from_limited(&mut raw.as_bytes(), 8)
+        };
+
+        assert_eq!(
+            chunked("4\r\nabcd\r\n4\r\nefgh\r\n0\r\n\r\n")
+                .unwrap()
+                .body(),
+            b"abcdefgh"
+        );
+        let cases = [
+            ("4\r\nabcd\r\n5\r\nefghi\r\n0\r\n\r\n", 413),
+            ("fffffffffffffffffffff\r\n", 413),
+            ("zz\r\n", 400),
+            ("4\r\nabcdef\r\n0\r\n\r\n", 400),
+            ("4\r\nab", 400),
+        ];
+        for (chunks, status) in cases {
+            assert_eq!(chunked(chunks).unwrap_err().status(), status, "{chunks:?}");
+        }
+    }
+}
@@ -0,0 +1,323 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use super::{Headers, Version, reason_phrase};
+
+/// Largest chunk written for a [`Body::Chunked`] body, in bytes.
+const CHUNK_SIZE: usize = 16 * 1024;
+
+/// Body of a [`Response`].
+pub enum Body {
+    /// Bytes held in memory.
+    Bytes(Vec<u8>),
+    /// `length` bytes read from `reader` while the response is written, so large files aren't
+    /// loaded into memory first.
+    Stream {
+        reader: Box<dyn Read + Send>,
+        length: u64,
+    },
+    /// Bytes read from the reader until it ends, for bodies whose length isn't known up front.
+    /// Sent with `Transfer-Encoding: chunked`, each read going out as a chunk as soon as it's
+    /// made.
+    Chunked(Box<dyn Read + Send>),
+}
+
+impl Body {
+    /// Chunked body made of `chunks` in order, for responses generated piece by piece.
+    pub fn from_chunks<I>(chunks: I) -> Body
+    where
+        I: IntoIterator<Item = Vec<u8>>,
+        I::IntoIter: Send + 'static,
+    {
+        Body::Chunked(Box::new(ChunkReader {
+            chunks: chunks.into_iter(),
+            current: Vec::new(),
+            read: 0,
+        }))
+    }
+
+    /// Number of bytes in the body, `None` for a chunked body.
+    pub fn len(&self) -> Option<u64> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes.len() as u64),
+            Body::Stream { length, .. } => Some(*length),
+            Body::Chunked(_) => None,
+        }
+    }
+
+    /// Returns `true` if the body is known to have no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == Some(0)
+    }
+
+    /// The bytes of an in-memory body, `None` for a stream.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Body::Bytes(bytes) => Some(bytes),
+            Body::Stream { .. } | Body::Chunked(_) => None,
+        }
+    }
+}
+
+impl From<Vec<u8>> for Body {
+    fn from(bytes: Vec<u8>) -> Body {
+        Body::Bytes(bytes)
+    }
+}
+
+impl From<String> for Body {
+    fn from(text: String) -> Body {
+        Body::Bytes(text.into_bytes())
+    }
+}
+
+impl From<&str> for Body {
+    fn from(text: &str) -> Body {
+        Body::Bytes(text.as_bytes().to_vec())
+    }
+}
+
+impl fmt::Debug for Body {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Body::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            Body::Stream { length, .. } => {
+                f.debug_struct("Stream").field("length", length).finish()
+            }
+            Body::Chunked(_) => f.write_str("Chunked(..)"),
+        }
+    }
+}
+
+/// Reads the chunks of [`Body::from_chunks`] back to back, one chunk per read at most so each
+/// goes out on its own.
+struct ChunkReader<I> {
+    chunks: I,
+    current: Vec<u8>,
+    read: usize,
+}
+
+impl<I: Iterator<Item = Vec<u8>>> Read for ChunkReader<I> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Skipping empty chunks, a read of 0 means the body has ended
+        while self.read == self.current.len() {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = chunk;
+                    self.read = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let n = buf.len().min(self.current.len() - self.read);
+        buf[..n].copy_from_slice(&self.current[self.read..self.read + n]);
+        self.read += n;
+
+        Ok(n)
+    }
+}
+
+/// An HTTP response, written to the connection with [`Response::write_to`].
+#[derive(Debug)]
+pub struct Response {
+    status: u16,
+    headers: Headers,
+    body: Body,
+}
+
+impl Response {
+    /// Creates a response with `status`, no headers and an empty body.
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Headers::new(),
+            body: Body::Bytes(Vec::new()),
+        }
+    }
+
+    /// Creates a `text/plain` response.
+    pub fn text(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/plain; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    /// Creates a `text/html` response.
+    pub fn html(status: u16, body: impl Into<String>) -> Response {
+        Response::new(status)
+            .with_header("Content-Type", "text/html; charset=utf-8")
+            .with_body(body.into())
+    }
+
+    /// Replaces the status code.
+    pub fn with_status(mut self, status: u16) -> Response {
+        self.status = status;
+        self
+    }
+
+    /// Sets the header `name` to `value`, replacing any earlier value.
+    pub fn with_header(mut self, name: impl Into<String>, value: impl Into<String>) -> Response {
+        self.headers.set(name, value);
+        self
+    }
+
+    /// Replaces the body.
+    pub fn with_body(mut self, body: impl Into<Body>) -> Response {
+        self.body = body.into();
+        self
+    }
+
+    /// Status code, e.g. `200`.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
+    }
+
+    pub fn body(&self) -> &Body {
+        &self.body
+    }
+
+    /// Returns `true` if the body is sent with `Transfer-Encoding: chunked`.
+    pub fn is_chunked(&self) -> bool {
+        matches!(self.body, Body::Chunked(_))
+    }
+
+    /// Writes the response to an HTTP/1.1 client, see [`Response::write_for`].
+    pub fn write_to(self, writer: &mut impl Write) -> io::Result<()> {
+        self.write_for(Version::Http11, writer)
+    }
+
+    /// Writes the status line, the headers, the framing headers for the body, and the body.
+    ///
+    /// A body of known length gets a `Content-Length`. A chunked body is sent with
+    /// `Transfer-Encoding: chunked`, except to HTTP/1.0 clients, which don't understand it and
+    /// get the bytes as they are; the caller has to close the connection afterwards so they
+    /// know where the body ends.
+    ///
+    /// A streamed body that ends before its length fails with
+    /// [`io::ErrorKind::UnexpectedEof`], the client will have seen a truncated response.
+    pub fn write_for(self, version: Version, writer: &mut impl Write) -> io::Result<()> {
+        let mut head = format!(
+            "HTTP/1.1 {} {}\r\n",
+            self.status,
+            reason_phrase(self.status)
+        );
+
+        for (name, value) in self.headers.iter() {
+            if !name.eq_ignore_ascii_case("Content-Length")
+                && !name.eq_ignore_ascii_case("Transfer-Encoding")
+            {
+                head.push_str(&format!("{name}: {value}\r\n"));
+            }
+        }
+
+        match self.body.len() {
+            Some(length) => head.push_str(&format!("Content-Length: {length}\r\n")),
+            None if version == Version::Http11 => {
+                head.push_str("Transfer-Encoding: chunked\r\n");
+            }
+            None => {}
+        }
+        head.push_str("\r\n");
+
+        let mut bytes = head.into_bytes();
+
+        match self.body {
+            Body::Bytes(body) => {
+                // One write for small responses, so the client doesn't see the head on its own
+                bytes.extend_from_slice(&body);
+                writer.write_all(&bytes)?;
+            }
+            Body::Stream { reader, length } => {
+                writer.write_all(&bytes)?;
+
+                if io::copy(&mut reader.take(length), writer)? < length {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+            Body::Chunked(mut reader) if version == Version::Http11 => {
+                writer.write_all(&bytes)?;
+                write_chunks(&mut reader, writer)?;
+            }
+            Body::Chunked(mut reader) => {
+                writer.write_all(&bytes)?;
+                io::copy(&mut reader, writer)?;
+            }
+        }
+
+        writer.flush()
+    }
+}
+
+/// Writes everything `reader` produces as chunks, then the zero-sized chunk that ends the body.
+fn write_chunks(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+    let mut buf = vec![0; CHUNK_SIZE];
+
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+
+        let mut chunk = format!("{n:X}\r\n").into_bytes();
+        chunk.extend_from_slice(&buf[..n]);
+        chunk.extend_from_slice(b"\r\n");
+
+        // Flushed chunk by chunk, a generated body should reach the client as it's produced
+        writer.write_all(&chunk)?;
+        writer.flush()?;
+    }
+
+    writer.write_all(b"0\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written(response: Response, version: Version) -> String {
+        let mut out = Vec::new();
+        response.write_for(version, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn chunked_bodies_are_framed_for_the_client_version() {
+        let chunked = || {
+            Response::new(200)
+                .with_header("Content-Length", "999")
+                .with_body(Body::from_chunks(vec![
+                    b"hello".to_vec(),
+                    Vec::new(),
+                    b", chunked world".to_vec(),
+                ]))
+        };
+
+        assert_eq!(
+            written(chunked(), Version::Http11),
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+             5\r\nhello\r\nF\r\n, chunked world\r\n0\r\n\r\n"
+        );
+        assert_eq!(
+            written(chunked(), Version::Http10),
+            "HTTP/1.1 200 OK\r\n\r\nhello, chunked world"
+        );
+        assert_eq!(
+            written(Response::text(404, "gone"), Version::Http11),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain; charset=utf-8\r\n\
+             Content-Length: 4\r\n\r\ngone"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+use std::fmt;
+
+use super::{Request, Response};
+
+type Handler = Box<dyn Fn(&Request, &Params) -> Response + Send + Sync + 'static>;
+
+/// Values captured from the path by a route's `:name` and `*name` segments.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    values: Vec<(String, String)>,
+}
+
+impl Params {
+    /// Value captured for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Every capture as `(name, value)`, in the order they appear in the pattern.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Param(String),
+    Rest(String),
+}
+
+struct Route {
+    method: String,
+    pattern: Vec<Segment>,
+    handler: Handler,
+}
+
+/// Sends each request to the handler registered for its method and path.
+///
+/// Patterns are paths whose segments can be literal, `:name` to capture one segment, or, as the
+/// last segment, `*name` to capture the rest of the path, which may be empty:
+///
+/// - `/users/:id` matches `/users/42` with `id` = `42`
+/// - `/static/*rest` matches `/static/css/site.css` with `rest` = `css/site.css`
+///
+/// Routes are tried in the order they were added and the first match wins. A path no route
+/// matches gets a 404, a path that matches only routes for other methods gets a 405 listing
+/// them in `Allow`.
+#[derive(Default)]
+pub struct Router {
+    routes: Vec<Route>,
+    not_found: Option<Handler>,
+}
+
+impl Router {
+    /// Creates a router without routes, which answers everything with 404.
+    pub fn new() -> Router {
+        Router::default()
+    }
+
+    /// Adds a route for `method` and `pattern`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pattern` doesn't start with `/`, has a `:` or `*` segment without a name, or
+    /// has a `*` segment anywhere but last.
+    pub fn route<F>(mut self, method: &str, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.routes.push(Route {
+            method: method.to_string(),
+            pattern: parse_pattern(pattern),
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Adds a `GET` route, see [`Router::route`].
+    pub fn get<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("GET", pattern, handler)
+    }
+
+    /// Adds a `POST` route, see [`Router::route`].
+    pub fn post<F>(self, pattern: &str, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.route("POST", pattern, handler)
+    }
+
+    /// Answers requests no route matches with `handler` instead of a plain 404.
+    pub fn not_found<F>(mut self, handler: F) -> Router
+    where
+        F: Fn(&Request, &Params) -> Response + Send + Sync + 'static,
+    {
+        self.not_found = Some(Box::new(handler));
+        self
+    }
+
+    /// Runs the handler for `request`, or answers 404 or 405 if there isn't one.
+    pub fn handle(&self, request: &Request) -> Response {
+        let mut allowed: Vec<&str> = Vec::new();
+
+        for route in &self.routes {
+            let Some(params) = match_path(&route.pattern, request.path()) else {
+                continue;
+            };
+
+            if route.method == request.method() {
+                return (route.handler)(request, &params);
+            }
+
+            if !allowed.contains(&route.method.as_str()) {
+                allowed.push(&route.method);
+            }
+        }
+
+        if allowed.is_empty() {
+            match &self.not_found {
+                Some(handler) => handler(request, &Params::default()),
+                None => Response::text(404, "404 Not Found\n"),
+            }
+        } else {
+            Response::text(405, "405 Method Not Allowed\n").with_header("Allow", allowed.join(", "))
+        }
+    }
+}
+
+impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(
+                self.routes
+                    .iter()
+                    .map(|route| (&route.method, &route.pattern)),
+            )
+            .finish()
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Vec<Segment> {
+    let rest = pattern
+        .strip_prefix('/')
+        .unwrap_or_else(|| panic!("route pattern {pattern:?} doesn't start with '/'"));
+
+    let segments: Vec<Segment> = rest
+        .split('/')
+        .map(|segment| {
+            let named = |name: &str| {
+                assert!(
+                    !name.is_empty(),
+                    "unnamed capture in route pattern {pattern:?}"
+                );
+                name.to_string()
+            };
+
+            if let Some(name) = segment.strip_prefix(':') {
+                Segment::Param(named(name))
+            } else if let Some(name) = segment.strip_prefix('*') {
+                Segment::Rest(named(name))
+            } else {
+                Segment::Literal(segment.to_string())
+            }
+        })
+        .collect();
+
+    let rest_position = segments.iter().position(|s| matches!(s, Segment::Rest(_)));
+    assert!(
+        rest_position.is_none_or(|i| i == segments.len() - 1),
+        "'*' capture before the end of route pattern {pattern:?}"
+    );
+
+    segments
+}
+
+fn match_path(pattern: &[Segment], path: &str) -> Option<Params> {
+    let mut segments = path.strip_prefix('/')?.split('/');
+    let mut values = Vec::new();
+
+    for segment in pattern {
+        match segment {
+            Segment::Literal(literal) => {
+                if segments.next()? != literal {
+                    return None;
+                }
+            }
+            Segment::Param(name) => {
+                let value = segments.next().filter(|value| !value.is_empty())?;
+                values.push((name.clone(), value.to_string()));
+            }
+            Segment::Rest(name) => {
+                let rest: Vec<&str> = segments.by_ref().collect();
+                values.push((name.clone(), rest.join("/")));
+            }
+        }
+    }
+
+    // The whole path has to be used up, a trailing `*name` always does
+    segments.next().is_none().then_some(Params { values })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(method: &str, target: &str) -> Request {
+        let raw = format!("{method} {target} HTTP/1.1\r\n\r\n");
+        Request::read_from(&mut raw.as_bytes()).unwrap()
+    }
+
+    fn echo(name: &'static str) -> impl Fn(&Request, &Params) -> Response {
+        move |_, params| {
+            let captures: Vec<String> = params.iter().map(|(k, v)| format!("{k}={v}")).collect();
+            Response::text(200, format!("{name} {}", captures.join(" ")))
+        }
+    }
+
+    fn body(response: &Response) -> &str {
+        std::str::from_utf8(response.body().as_bytes().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn routes_match_literals_params_and_rest() {
+        let router = Router::new()
+            .get("/", echo("index"))
+            .get("/users/:id", echo("user"))
+            .get("/users/:id/posts/:post", echo("post"))
+            .get("/static/*rest", echo("static"));
+
+        let cases = [
+            ("/", "index "),
+            ("/users/42", "user id=42"),
+            ("/users/42/posts/7", "post id=42 post=7"),
+            ("/static/css/site.css", "static rest=css/site.css"),
+            ("/static", "static rest="),
+        ];
+        for (target, expected) in cases {
+            let response = router.handle(&request("GET", target));
+            assert_eq!(
+                (response.status(), body(&response)),
+                (200, expected),
+                "{target}"
+            );
+        }
+
+        for target in ["/users", "/users/", "/users/42/extra", "/nope"] {
+            assert_eq!(
+                router.handle(&request("GET", target)).status(),
+                404,
+                "{target}"
+            );
+        }
+    }
+
+    #[test]
+    fn wrong_method_gets_405_with_allow() {
+        let router = Router::new()
+            .get("/items/:id", echo("get"))
+            .route("DELETE", "/items/:id", echo("delete"))
+            .post("/items", echo("create"));
+
+        let response = router.handle(&request("PUT", "/items/3"));
+        assert_eq!(response.status(), 405);
+        assert_eq!(response.headers().get("allow"), Some("GET, DELETE"));
+
+        let response = router.handle(&request("DELETE", "/items/3"));
+        assert_eq!(body(&response), "delete id=3");
+    }
+
+    #[test]
+    #[should_panic(expected = "before the end")]
+    fn rest_capture_must_be_last() {
+        let _ = Router::new().get("/files/*path/edit", echo("edit"));
+    }
+}
@@ -1,14 +1,193 @@
 use std::{
-    sync::{Arc, Mutex, mpsc},
+    error::Error,
+    fmt, io,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, OnceLock, PoisonError, mpsc},
     thread,
+    time::Instant,
 };
 
+mod affinity;
+mod builder;
+mod cancel;
+mod executor;
+mod future;
+mod graph;
+mod group;
+mod handle;
+pub mod http;
+mod observer;
+mod parallel;
+mod queue;
+mod schedule;
+mod scope;
+mod shutdown;
+mod signal;
+mod stats;
+
+pub use builder::{ThreadPoolBuilder, with_worker_state};
+pub use cancel::CancelToken;
+pub use executor::{Executor, TestExecutor};
+pub use future::block_on;
+pub use graph::{GraphCycle, GraphReport, TaskGraph, TaskId};
+pub use group::JobGroup;
+pub use handle::{JobError, JobHandle};
+pub use observer::{LogObserver, NoopObserver, PoolObserver};
+pub use parallel::Parallel;
+pub use queue::{OverflowPolicy, Priority};
+pub use schedule::ScheduledJob;
+pub use scope::Scope;
+pub use shutdown::ShutdownTimeout;
+pub use signal::ShutdownSignal;
+pub use stats::{Histogram, PoolStats};
+
+use builder::WorkerConfig;
+use group::Latch;
+use queue::{JobQueue, Push, TaskOptions};
+use schedule::Timer;
+use stats::Metrics;
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: Option<mpsc::Sender<Job>>,
+    shared: Arc<Shared>,
+    timer: OnceLock<Timer>,
 }
 
-type Job = Box<dyn FnOnce() + Send + 'static>;
+/// State every worker thread holds on to, including replacements spawned after a worker dies.
+struct Shared {
+    queue: JobQueue,
+    policy: OverflowPolicy,
+    config: WorkerConfig,
+    observer: Arc<dyn PoolObserver>,
+    metrics: Metrics,
+    /// Jobs queued or running.
+    outstanding: Latch,
+    /// Which workers have left their loop for good, indexed by worker id.
+    exited: Mutex<Vec<bool>>,
+    worker_exited: Condvar,
+}
+
+impl Shared {
+    /// Queues `f`, see [`ThreadPool::execute`].
+    fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.execute_with(f, TaskOptions::default());
+    }
+
+    fn execute_with<F>(&self, f: F, options: TaskOptions)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match self.push(f, options, self.policy) {
+            Push::Queued => {}
+            Push::Evicted(task) => {
+                self.metrics.job_rejected();
+
+                drop(task);
+            }
+            Push::Full(f) => {
+                if self.policy == OverflowPolicy::CallerRuns {
+                    f();
+                } else {
+                    self.metrics.job_rejected();
+                }
+            }
+        }
+    }
+
+    /// Pushes onto the queue, keeping `outstanding` in step with it.
+    fn push<F>(&self, f: F, options: TaskOptions, policy: OverflowPolicy) -> Push<F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        // Counted up front, a worker could otherwise finish the job before it's counted
+        self.outstanding.add();
+
+        let push = self.queue.push(f, options, policy);
+
+        match push {
+            Push::Queued => {}
+            // Either the new job didn't go in or an old one came out
+            Push::Evicted(_) | Push::Full(_) => self.outstanding.done(),
+        }
+
+        push
+    }
+
+    /// Tells the observer about an event. A panicking observer is ignored, it mustn't cost a
+    /// job or a worker.
+    fn observe(&self, event: impl FnOnce(&dyn PoolObserver)) {
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| event(self.observer.as_ref())));
+    }
+
+    fn mark_exited(&self, id: usize) {
+        lock(&self.exited)[id] = true;
+
+        self.worker_exited.notify_all();
+    }
+}
+
+/// A job queued on a [`ThreadPool`], as handed back by [`ThreadPool::shutdown_now`].
+pub type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Error returned by [`ThreadPool::build`] when a pool can't be created.
+#[derive(Debug)]
+pub enum PoolCreationError {
+    /// A pool needs at least one thread.
+    ZeroSize,
+    /// A bounded queue needs room for at least one job.
+    ZeroCapacity,
+    /// The operating system refused to spawn the thread for worker `id`.
+    Spawn { id: usize, source: io::Error },
+    /// The kernel rejected the cores worker `id` was to be pinned to, see
+    /// [`ThreadPoolBuilder::pin_to_cores`].
+    Affinity { id: usize, source: io::Error },
+    /// The kernel rejected the nice level for worker `id`, see [`ThreadPoolBuilder::nice`].
+    Nice { id: usize, source: io::Error },
+    /// The start hook or init closure panicked on worker `id`, with the panic message if it was
+    /// a string, see [`ThreadPoolBuilder::on_thread_start`] and
+    /// [`ThreadPoolBuilder::worker_init`].
+    WorkerStart { id: usize, message: Option<String> },
+}
+
+impl fmt::Display for PoolCreationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolCreationError::ZeroSize => write!(f, "thread pool size must be greater than zero"),
+            PoolCreationError::ZeroCapacity => {
+                write!(f, "thread pool queue capacity must be greater than zero")
+            }
+            PoolCreationError::Spawn { id, source } => {
+                write!(f, "failed to spawn thread for worker {id}: {source}")
+            }
+            PoolCreationError::Affinity { id, source } => {
+                write!(f, "failed to pin worker {id} to its cores: {source}")
+            }
+            PoolCreationError::Nice { id, source } => {
+                write!(f, "failed to set the nice level of worker {id}: {source}")
+            }
+            PoolCreationError::WorkerStart { id, message } => match message {
+                Some(message) => write!(f, "worker {id} panicked while starting: {message}"),
+                None => write!(f, "worker {id} panicked while starting"),
+            },
+        }
+    }
+}
+
+impl Error for PoolCreationError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PoolCreationError::ZeroSize
+            | PoolCreationError::ZeroCapacity
+            | PoolCreationError::WorkerStart { .. } => None,
+            PoolCreationError::Spawn { source, .. }
+            | PoolCreationError::Affinity { source, .. }
+            | PoolCreationError::Nice { source, .. } => Some(source),
+        }
+    }
+}
 
 impl ThreadPool {
     /// Create a new ThreadPool.
@@ -17,73 +196,486 @@ impl ThreadPool {
     ///
     /// # Panics
     ///
-    /// The `new` function will panic if the size is zero
+    /// The `new` function will panic if the size is zero or if a worker thread can't be spawned,
+    /// use [`ThreadPool::build`] to handle these cases instead
     pub fn new(size: usize) -> ThreadPool {
-        assert!(size > 0);
+        match ThreadPool::build(size) {
+            Ok(pool) => pool,
+            Err(e) => panic!("{e}"),
+        }
+    }
 
-        let (sender, receiver) = mpsc::channel();
+    /// Create a new ThreadPool, returning an error instead of panicking.
+    ///
+    /// Fails with [`PoolCreationError::ZeroSize`] if the size is zero and with
+    /// [`PoolCreationError::Spawn`] if a worker thread can't be spawned, any workers that were
+    /// already started are shut down and joined before the error is returned
+    pub fn build(size: usize) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder().num_threads(size).build()
+    }
 
-        let receiver = Arc::new(Mutex::new(receiver));
+    /// Create a new ThreadPool whose queue holds at most `capacity` waiting jobs.
+    ///
+    /// `policy` decides what [`ThreadPool::execute`] does with a job that arrives while the queue
+    /// is full, [`ThreadPool::try_execute`] always hands such a job back instead. Fails like
+    /// [`ThreadPool::build`], and with [`PoolCreationError::ZeroCapacity`] if `capacity` is zero
+    pub fn build_bounded(
+        size: usize,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Result<ThreadPool, PoolCreationError> {
+        ThreadPool::builder()
+            .num_threads(size)
+            .queue_capacity(capacity)
+            .overflow_policy(policy)
+            .build()
+    }
 
-        let mut workers = Vec::with_capacity(size);
+    /// Returns a [`ThreadPoolBuilder`] for configuring thread names, stack size, hooks and more.
+    pub fn builder() -> ThreadPoolBuilder {
+        ThreadPoolBuilder::new()
+    }
 
-        for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)));
-        }
+    /// Queues `f` to run on one of the pool's threads.
+    ///
+    /// If the queue is bounded and full, what happens depends on the pool's [`OverflowPolicy`]:
+    /// the call blocks, drops `f` or the oldest queued job, or runs `f` on the calling thread.
+    /// Dropped jobs are counted by [`ThreadPool::rejected_jobs`].
+    pub fn execute<F>(&self, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.execute(f);
+    }
 
-        ThreadPool { 
-            workers, 
-            sender: Some(sender) ,
-        }
+    /// Queues `f` with the given [`Priority`], [`ThreadPool::execute`] uses `Normal`.
+    ///
+    /// Higher priority jobs are picked up first, but a job's priority only counts for so long:
+    /// after waiting one aging step (see [`ThreadPoolBuilder::priority_aging`]) a job is treated
+    /// like a newly queued job one level up, so low priority work still gets to run under load.
+    ///
+    /// `Normal` jobs queued by a job running on the pool wait on their worker's own deque. A
+    /// worker checks for `High` jobs before taking from it, but `Normal` and `Low` jobs from
+    /// outside the pool only run once that backlog is done or stolen.
+    pub fn execute_with_priority<F>(&self, priority: Priority, f: F)
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.shared.execute_with(
+            f,
+            TaskOptions {
+                priority,
+                ..TaskOptions::default()
+            },
+        );
     }
 
-    pub fn execute<F>(&self, f: F)
+    /// Queues `f` unless the queue is full, in which case `f` is handed back untouched.
+    ///
+    /// Never blocks and ignores the pool's [`OverflowPolicy`]. Always succeeds on an unbounded
+    /// pool.
+    pub fn try_execute<F>(&self, f: F) -> Result<(), F>
     where
         F: FnOnce() + Send + 'static,
     {
-        let job = Box::new(f);
+        match self
+            .shared
+            .push(f, TaskOptions::default(), OverflowPolicy::Reject)
+        {
+            Push::Queued => Ok(()),
+            Push::Evicted(_) => unreachable!("`Reject` never evicts a queued job"),
+            Push::Full(f) => Err(f),
+        }
+    }
+
+    /// Runs `f` on the pool and returns a [`JobHandle`] for the value it returns.
+    ///
+    /// A panic inside `f` is caught and reported through the handle as
+    /// [`JobError::Panicked`] rather than taking down the worker.
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+
+        self.execute(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f));
+
+            // The caller may have dropped the handle, nobody is left to tell
+            let _ = sender.send(result);
+        });
+
+        JobHandle::new(receiver)
+    }
+
+    /// Number of jobs passed to [`ThreadPool::execute`] that panicked.
+    ///
+    /// A panicking job doesn't take its worker down, the panic is caught and counted here and the
+    /// worker moves on to the next job. Panics in jobs started with [`ThreadPool::spawn`] are
+    /// reported through their [`JobHandle`] instead.
+    pub fn panicked_jobs(&self) -> usize {
+        self.shared.metrics.panicked_jobs()
+    }
+
+    /// Number of jobs dropped because the queue was full, under [`OverflowPolicy::Reject`] or
+    /// [`OverflowPolicy::DropOldest`].
+    pub fn rejected_jobs(&self) -> usize {
+        self.shared.metrics.rejected_jobs()
+    }
 
-        self.sender.as_ref().unwrap().send(job).unwrap();
+    /// Takes a snapshot of the pool's queue depth, worker activity and job timings.
+    pub fn stats(&self) -> PoolStats {
+        self.shared
+            .metrics
+            .snapshot(self.workers.len(), self.shared.queue.len())
+    }
+}
+
+impl ThreadPool {
+    /// Stops the timer and closes the queue, telling the observer the first time round.
+    fn close(&mut self) {
+        self.stop_timer();
+
+        if self.shared.queue.close() {
+            self.shared.observe(|observer| observer.on_shutdown());
+        }
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        drop(self.sender.take());
+        self.close();
 
         for worker in self.workers.drain(..) {
-            println!("Shutting down worker: {}", worker.id);
-
-            worker.thread.join().unwrap();
+            worker.join();
         }
     }
 }
 
+/// Locks `mutex`, ignoring poisoning.
+///
+/// Jobs never run while one of the pool's locks is held, so a poisoned lock can't have left the
+/// data behind it half-updated.
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+type ThreadSlot = Arc<Mutex<Option<thread::JoinHandle<()>>>>;
+
 struct Worker {
     id: usize,
-    thread: thread::JoinHandle<()>,
+    thread: ThreadSlot,
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<mpsc::Receiver<Job>>>) -> Worker {
-        let thread = thread::spawn(move || {
+    /// Spawns the worker and waits for it to report whether it could be placed on its cores
+    /// and got through its start hooks.
+    fn new(id: usize, shared: Arc<Shared>) -> Result<Worker, PoolCreationError> {
+        let thread: ThreadSlot = Arc::new(Mutex::new(None));
+        let (placed, placement) = mpsc::channel();
+
+        // Hold the slot while spawning so a thread that dies straight away can't store its
+        // replacement before the original handle lands here
+        let mut slot = lock(&thread);
+        *slot = Some(
+            Worker::spawn(id, shared, Arc::clone(&thread), Some(placed))
+                .map_err(|source| PoolCreationError::Spawn { id, source })?,
+        );
+        drop(slot);
+
+        let worker = Worker { id, thread };
+
+        match placement.recv() {
+            Ok(Err(err)) => {
+                // The thread exits without taking jobs once it has reported the error
+                worker.join();
+                Err(err)
+            }
+            _ => Ok(worker),
+        }
+    }
+
+    fn join(self) {
+        // A worker thread that died is replaced in the same slot before it finishes unwinding,
+        // so keep joining until the slot stays empty
+        while let Some(thread) = lock(&self.thread).take() {
+            let _ = thread.join();
+        }
+    }
+
+    /// Spawns the thread for worker `id`, which reports on `placed` whether pinning it, setting
+    /// its nice level and running the start hook and init closure worked.
+    ///
+    /// A replacement for a worker that died has no one to report to, it runs wherever it lands
+    /// but still exits if its start hooks panic.
+    fn spawn(
+        id: usize,
+        shared: Arc<Shared>,
+        slot: ThreadSlot,
+        placed: Option<mpsc::Sender<Result<(), PoolCreationError>>>,
+    ) -> io::Result<thread::JoinHandle<()>> {
+        shared.config.thread_builder(id).spawn(move || {
+            let mut sentinel = Sentinel {
+                id,
+                shared: Arc::clone(&shared),
+                slot,
+                started: false,
+            };
+
+            let mut placement = shared.config.place_thread(id);
+            if placed.is_none() {
+                placement = Ok(());
+            }
+
+            shared.queue.register_worker(id);
+
+            // Reported once the start hook has run, so `build` sees a worker that can't start
+            let started = placement.and_then(|()| shared.config.thread_started(id));
+            let failed = started.is_err();
+            if let Some(placed) = placed {
+                let _ = placed.send(started);
+            }
+            if failed {
+                return;
+            }
+
+            sentinel.started = true;
+
             loop {
-                let message = receiver.lock().unwrap().recv();
+                let message = shared.queue.pop(id);
 
                 match message {
-                    Ok(job) => {
-                        println!("Worker {id} got a job, executing...");
-
-                        job();
+                    Some(task) if task.is_cancelled() => {
+                        shared.metrics.job_cancelled();
+                        shared.outstanding.done();
                     }
-                    Err(_) => {
-                        println!("Worker {id} disconnected; shutting down");
-                        break;
+                    Some(task) => {
+                        shared.observe(|observer| observer.on_job_start(id));
+
+                        let started = Instant::now();
+                        shared
+                            .metrics
+                            .job_started(started.duration_since(task.queued_at));
+
+                        let result = panic::catch_unwind(AssertUnwindSafe(task.job));
+                        let elapsed = started.elapsed();
+
+                        shared.metrics.job_finished(elapsed, result.is_err());
+                        shared.outstanding.done();
+
+                        if let Err(payload) = &result {
+                            shared.observe(|observer| observer.on_job_panic(id, payload.as_ref()));
+                        }
+                        shared.observe(|observer| observer.on_job_end(id, elapsed));
                     }
+                    None => break,
                 }
             }
+        })
+    }
+}
+
+/// Runs the stop hook when a worker thread ends, and spawns a replacement if it ended by
+/// unwinding, so the pool keeps its configured size.
+///
+/// Job and observer panics are caught in the worker loop, this covers anything else that
+/// unwinds out of it.
+struct Sentinel {
+    id: usize,
+    shared: Arc<Shared>,
+    slot: ThreadSlot,
+    /// Whether the start hook and worker init finished, a thread that panicked in them would
+    /// only do it again if replaced.
+    started: bool,
+}
+
+impl Drop for Sentinel {
+    fn drop(&mut self) {
+        if self.started {
+            let config = &self.shared.config;
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| config.thread_stopped(self.id)));
+        }
+
+        let id = self.id;
+        self.shared.observe(|observer| observer.on_worker_exit(id));
+
+        if !thread::panicking() || !self.started {
+            self.shared.mark_exited(self.id);
+            return;
+        }
+
+        let mut slot = lock(&self.slot);
+
+        // If the thread can't be replaced the pool runs one worker short, there is no caller to
+        // report the error to from here
+        match Worker::spawn(
+            self.id,
+            Arc::clone(&self.shared),
+            Arc::clone(&self.slot),
+            None,
+        ) {
+            Ok(thread) => *slot = Some(thread),
+            Err(_) => self.shared.mark_exited(self.id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, Ordering},
+        time::Duration,
+    };
+
+    /// Parks one of `pool`'s workers on a job that returns once the sender is used or dropped.
+    ///
+    /// Only returns when the job has started, so on a single worker pool anything queued after
+    /// this stays queued until it's released.
+    pub(crate) fn parked(pool: &ThreadPool) -> mpsc::Sender<()> {
+        let (started_tx, started_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        pool.execute(move || {
+            started_tx.send(()).unwrap();
+            let _ = release_rx.recv();
+        });
+        started_rx.recv().unwrap();
+
+        release_tx
+    }
+
+    #[test]
+    fn build_rejects_zero_size() {
+        assert!(matches!(
+            ThreadPool::build(0),
+            Err(PoolCreationError::ZeroSize)
+        ));
+    }
+
+    #[test]
+    fn build_runs_jobs() {
+        let pool = ThreadPool::build(2).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        for i in 0..4 {
+            let tx = tx.clone();
+            pool.execute(move || tx.send(i).unwrap());
+        }
+        drop(tx);
+
+        let mut results: Vec<_> = rx.iter().collect();
+        results.sort();
+        assert_eq!(results, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn spawn_returns_value_and_reports_panics() {
+        let pool = ThreadPool::build(2).unwrap();
+
+        let ok = pool.spawn(|| 6 * 7);
+        let bad = pool.spawn(|| -> i32 { panic!("boom") });
+
+        assert_eq!(ok.join().unwrap(), 42);
+
+        let err = bad.join().unwrap_err();
+        assert!(err.is_panic());
+        assert_eq!(err.to_string(), "job panicked: boom");
+    }
+
+    #[test]
+    fn panicking_jobs_are_counted_and_worker_survives() {
+        let pool = ThreadPool::build(1).unwrap();
+
+        pool.execute(|| panic!("first"));
+        pool.execute(|| panic!("second"));
+
+        // The single worker has to survive both panics to run this
+        assert_eq!(
+            pool.spawn(|| "still running").join().unwrap(),
+            "still running"
+        );
+        assert_eq!(pool.panicked_jobs(), 2);
+    }
+
+    #[test]
+    fn bounded_queue_applies_overflow_policy() {
+        // A pool with room for one queued job, its only worker parked until the sender is used
+        let parked_pool = |policy| {
+            let pool = ThreadPool::build_bounded(1, 1, policy).unwrap();
+            let release_tx = parked(&pool);
+            (pool, release_tx)
+        };
+
+        for (policy, expected) in [
+            (OverflowPolicy::DropOldest, vec![2]),
+            (OverflowPolicy::Reject, vec![0]),
+        ] {
+            let (pool, release_tx) = parked_pool(policy);
+            let (tx, rx) = mpsc::channel();
+            for i in 0..3 {
+                let tx = tx.clone();
+                pool.execute(move || tx.send(i).unwrap());
+            }
+            drop(tx);
+
+            assert!(pool.try_execute(|| {}).is_err());
+            assert_eq!(pool.rejected_jobs(), 2, "{policy:?}");
+
+            release_tx.send(()).unwrap();
+            assert_eq!(rx.iter().collect::<Vec<_>>(), expected, "{policy:?}");
+        }
+
+        let (pool, release_tx) = parked_pool(OverflowPolicy::CallerRuns);
+        pool.execute(|| {});
+        let (tx, rx) = mpsc::channel();
+        pool.execute(move || tx.send(thread::current().id()).unwrap());
+        assert_eq!(rx.recv().unwrap(), thread::current().id());
+        assert_eq!(pool.rejected_jobs(), 0);
+        release_tx.send(()).unwrap();
+
+        let (pool, release_tx) = parked_pool(OverflowPolicy::Block);
+        pool.execute(|| {});
+        let queued = AtomicBool::new(false);
+        thread::scope(|s| {
+            let blocked = s.spawn(|| {
+                pool.execute(|| {});
+                queued.store(true, Ordering::SeqCst);
+            });
+
+            thread::sleep(Duration::from_millis(20));
+            assert!(!queued.load(Ordering::SeqCst));
+
+            release_tx.send(()).unwrap();
+            blocked.join().unwrap();
         });
+        assert!(queued.load(Ordering::SeqCst));
+        assert_eq!(pool.rejected_jobs(), 0);
+    }
+
+    #[test]
+    fn jobs_submitted_from_workers_all_run() {
+        let pool = Arc::new(ThreadPool::build(4).unwrap());
+        let (tx, rx) = mpsc::channel();
+
+        for i in 0..8 {
+            let inner = Arc::clone(&pool);
+            let tx = tx.clone();
+
+            // Each job fans out onto its worker's local deque, idle workers have to steal
+            let handle = pool.spawn(move || {
+                for j in 0..8 {
+                    let tx = tx.clone();
+                    inner.execute(move || tx.send(i * 8 + j).unwrap());
+                }
+            });
+            handle.join().unwrap();
+        }
+        drop(tx);
 
-        Worker { id, thread }
+        let mut results: Vec<_> = rx.iter().collect();
+        results.sort();
+        assert_eq!(results, (0..64).collect::<Vec<_>>());
     }
 }
@@ -1,47 +1,162 @@
 use std::{
-    fs,
-    io::{BufReader, prelude::*},
-    net::{TcpListener, TcpStream},
+    collections::HashMap,
+    env,
+    net::{Shutdown, TcpListener, TcpStream},
+    process,
+    sync::{Arc, Mutex},
     thread,
     time::Duration,
 };
 
-use hello::ThreadPool;
+use hello::{
+    OverflowPolicy, ShutdownSignal, ThreadPool,
+    http::{self, KeepAlive, Router, StaticFiles},
+};
+
+/// How long connections that are being served get to finish once shutdown starts, after that
+/// `main` stops waiting on them and leaves the rest to `Drop for ThreadPool`, which joins the
+/// workers once their current request is answered.
+const GRACE_PERIOD: Duration = Duration::from_secs(10);
+
+/// Pause after a failed accept, so errors like running out of file descriptors don't spin.
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
 
 fn main() {
     let listener = TcpListener::bind("127.0.0.1:7878").unwrap();
 
-   let pool = ThreadPool::new(4);
+    let signal = ShutdownSignal::install().unwrap_or_else(|err| {
+        eprintln!("Problem installing signal handlers: {err}");
+        process::exit(1);
+    });
+
+    // Once 64 connections are waiting, stop accepting new ones until a worker frees up
+    let pool = ThreadPool::build_bounded(4, 64, OverflowPolicy::Block).unwrap_or_else(|err| {
+        eprintln!("Problem creating thread pool: {err}");
+        process::exit(1);
+    });
 
-    for stream in listener.incoming().take(2) {
-        let stream = stream.unwrap();
+    // Files are served from the directory given as the first argument, `public` by default
+    let root = env::args().nth(1).unwrap_or_else(|| "public".to_string());
+    let router = Arc::new(routes(StaticFiles::new(root)));
+    let connections = Arc::new(Connections::default());
+    let served = pool.group();
 
-        pool.execute(|| {
-            handle_connection(stream);
+    // Runs until SIGINT or SIGTERM
+    loop {
+        let stream = match signal.accept(&listener) {
+            Ok(Some((stream, _))) => stream,
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("Failed to accept connection: {err}");
+                thread::sleep(ACCEPT_BACKOFF);
+                continue;
+            }
+        };
+
+        let router = Arc::clone(&router);
+        let connections = Arc::clone(&connections);
+
+        served.execute(move || {
+            let _open = connections.track(&stream);
+            handle_connection(stream, &router);
         });
     }
 
     println!("Shutting down...");
+
+    // Stop accepting, and stop open connections from waiting for another request
+    drop(listener);
+    connections.close();
+
+    if !served.wait_timeout(GRACE_PERIOD) {
+        eprintln!(
+            "Shutdown grace period ran out with {} connection(s) unfinished",
+            served.pending()
+        );
+    }
+
+    // Joins the workers, including any still finishing a request past the grace period
+    drop(served);
+    drop(pool);
 }
 
-fn handle_connection(mut stream: TcpStream) {
-    let buf_reader = BufReader::new(&stream);
-    let request_line = buf_reader.lines().next().unwrap().unwrap();
+/// Connections being served, so shutdown can stop them waiting for more requests.
+#[derive(Default)]
+struct Connections {
+    state: Mutex<ConnectionsState>,
+}
 
-    let (status_line, filename) = match &request_line[..] {
-        "GET / HTTP/1.1" => ("HTTP/1.1 200 OK", "hello.html"),
-        "GET /sleep HTTP/1.1" => {
-            thread::sleep(Duration::from_secs(5));
+#[derive(Default)]
+struct ConnectionsState {
+    next_id: usize,
+    open: HashMap<usize, TcpStream>,
+    closing: bool,
+}
+
+impl Connections {
+    /// Registers `stream` until the returned guard is dropped.
+    fn track(&self, stream: &TcpStream) -> Option<Tracked<'_>> {
+        let stream = stream.try_clone().ok()?;
+        let mut state = self.state.lock().unwrap();
 
-            ("HTTP/1.1 200 OK", "hello.html")
+        if state.closing {
+            let _ = stream.shutdown(Shutdown::Read);
+            return None;
         }
-        _ => ("HTTP/1.1 404 NOT FOUND", "404.html")
-    };
 
-    let contents = fs::read_to_string(filename).unwrap();
-    let length = contents.len();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.open.insert(id, stream);
+
+        Some(Tracked {
+            connections: self,
+            id,
+        })
+    }
 
-    let response = format!("{status_line}\r\nContent-Length: {length}\r\n\r\n{contents}");
+    /// Shuts the read side of every connection, a request that has already arrived is still
+    /// read and answered, after that the connection sees end of input and closes.
+    fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closing = true;
 
-    stream.write_all(response.as_bytes()).unwrap();
+        for stream in state.open.values() {
+            let _ = stream.shutdown(Shutdown::Read);
+        }
+    }
+}
+
+struct Tracked<'a> {
+    connections: &'a Connections,
+    id: usize,
+}
+
+impl Drop for Tracked<'_> {
+    fn drop(&mut self) {
+        self.connections.state.lock().unwrap().open.remove(&self.id);
+    }
+}
+
+fn routes(files: StaticFiles) -> Router {
+    let (index, sleep) = (files.clone(), files.clone());
+
+    Router::new()
+        .get("/", move |_, _| index.serve("hello.html"))
+        .get("/sleep", move |_, _| {
+            thread::sleep(Duration::from_secs(5));
+
+            sleep.serve("hello.html")
+        })
+        .get("/*path", move |_, params| {
+            match files.serve(params.get("path").unwrap_or_default()) {
+                response if response.status() == 404 => files.serve("404.html").with_status(404),
+                response => response,
+            }
+        })
+}
+
+fn handle_connection(stream: TcpStream, router: &Router) {
+    if let Err(err) = http::serve_connection(&stream, router, &KeepAlive::default()) {
+        eprintln!("Connection error: {err}");
+    }
 }
@@ -0,0 +1,231 @@
+use std::{
+    any::Any,
+    fs::{File, OpenOptions},
+    io::{self, LineWriter, Write},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{handle::panic_message, lock};
+
+/// Receives events from a [`ThreadPool`](crate::ThreadPool)'s workers, set with
+/// [`ThreadPoolBuilder::observer`](crate::ThreadPoolBuilder::observer).
+///
+/// Every method does nothing by default, so an implementation only overrides the events it
+/// cares about. Methods are called on the worker thread right next to the job, so they should
+/// be quick. A panic in one is caught and ignored, the job and the worker carry on.
+pub trait PoolObserver: Send + Sync {
+    /// Worker `worker` is about to run a job.
+    fn on_job_start(&self, worker: usize) {
+        let _ = worker;
+    }
+
+    /// Worker `worker` finished a job that ran for `elapsed`, whether or not it panicked.
+    fn on_job_end(&self, worker: usize, elapsed: Duration) {
+        let _ = (worker, elapsed);
+    }
+
+    /// The job on worker `worker` panicked with `payload`, called before
+    /// [`PoolObserver::on_job_end`].
+    fn on_job_panic(&self, worker: usize, payload: &(dyn Any + Send)) {
+        let _ = (worker, payload);
+    }
+
+    /// Worker `worker`'s thread is exiting.
+    fn on_worker_exit(&self, worker: usize) {
+        let _ = worker;
+    }
+
+    /// The pool has stopped accepting jobs and is shutting down.
+    fn on_shutdown(&self) {}
+}
+
+/// Observer that ignores every event, the default for a new pool.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopObserver;
+
+impl PoolObserver for NoopObserver {}
+
+/// Observer that appends a line per event to a log file.
+///
+/// Lines start with the seconds since the Unix epoch, e.g.
+/// `[1760745600.123] worker 2 job panicked: index out of bounds`.
+pub struct LogObserver {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl LogObserver {
+    /// Opens `path` for appending, creating it if it doesn't exist.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<LogObserver> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(LogObserver {
+            file: Mutex::new(LineWriter::new(file)),
+        })
+    }
+
+    fn log(&self, message: &str) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        // Losing a log line isn't worth taking a worker down over
+        let _ = writeln!(
+            lock(&self.file),
+            "[{}.{:03}] {message}",
+            now.as_secs(),
+            now.subsec_millis()
+        );
+    }
+}
+
+impl PoolObserver for LogObserver {
+    fn on_job_start(&self, worker: usize) {
+        self.log(&format!("worker {worker} started a job"));
+    }
+
+    fn on_job_end(&self, worker: usize, elapsed: Duration) {
+        self.log(&format!("worker {worker} finished a job in {elapsed:?}"));
+    }
+
+    fn on_job_panic(&self, worker: usize, payload: &(dyn Any + Send)) {
+        match panic_message(payload) {
+            Some(msg) => self.log(&format!("worker {worker} job panicked: {msg}")),
+            None => self.log(&format!("worker {worker} job panicked")),
+        }
+    }
+
+    fn on_worker_exit(&self, worker: usize) {
+        self.log(&format!("worker {worker} exited"));
+    }
+
+    fn on_shutdown(&self) {
+        self.log("shutting down");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::{
+        env, fs, process,
+        sync::{
+            Arc,
+            atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    #[test]
+    fn log_observer_writes_a_timestamped_line_per_event() {
+        let path = env::temp_dir().join(format!("hello-observer-{}.log", process::id()));
+        let _ = fs::remove_file(&path);
+
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .observer(Arc::new(LogObserver::create(&path).unwrap()))
+            .build()
+            .unwrap();
+        pool.execute(|| panic!("logged"));
+        drop(pool);
+
+        let log = fs::read_to_string(&path).unwrap();
+        let mut messages = Vec::new();
+        for line in log.lines() {
+            let (stamp, message) = line.split_once("] ").unwrap();
+            let (secs, millis) = stamp.strip_prefix('[').unwrap().split_once('.').unwrap();
+            assert!(secs.parse::<u64>().is_ok() && millis.len() == 3, "{line}");
+            messages.push(message);
+        }
+
+        // Shutdown is logged from the thread dropping the pool, so it can land anywhere
+        let shutdown = messages.iter().position(|&m| m == "shutting down").unwrap();
+        messages.remove(shutdown);
+
+        assert_eq!(messages.len(), 4, "{messages:?}");
+        assert_eq!(messages[0], "worker 0 started a job");
+        assert_eq!(messages[1], "worker 0 job panicked: logged");
+        assert!(messages[2].starts_with("worker 0 finished a job in "));
+        assert_eq!(messages[3], "worker 0 exited");
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn observer_sees_job_and_worker_events() {
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl PoolObserver for Recorder {
+            fn on_job_start(&self, worker: usize) {
+                lock(&self.0).push(format!("start {worker}"));
+            }
+
+            fn on_job_panic(&self, worker: usize, _: &(dyn std::any::Any + Send)) {
+                lock(&self.0).push(format!("panic {worker}"));
+            }
+
+            fn on_worker_exit(&self, worker: usize) {
+                lock(&self.0).push(format!("exit {worker}"));
+            }
+
+            fn on_shutdown(&self) {
+                lock(&self.0).push("shutdown".to_string());
+            }
+        }
+
+        let recorder = Arc::new(Recorder::default());
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .observer(recorder.clone())
+            .build()
+            .unwrap();
+
+        pool.execute(|| panic!("observed"));
+        pool.shutdown(Duration::from_secs(5)).unwrap();
+
+        // Shutdown is reported from the caller's thread, so it can land anywhere among the rest
+        let mut events = lock(&recorder.0).clone();
+        let shutdown = events.iter().position(|e| e == "shutdown").unwrap();
+        events.remove(shutdown);
+        assert_eq!(events, vec!["start 0", "panic 0", "exit 0"]);
+    }
+
+    #[test]
+    fn panicking_observer_loses_no_jobs() {
+        struct Flaky(AtomicUsize);
+
+        impl PoolObserver for Flaky {
+            fn on_job_start(&self, _: usize) {
+                if self.0.fetch_add(1, Ordering::SeqCst) < 2 {
+                    panic!("observer");
+                }
+            }
+
+            fn on_job_end(&self, _: usize, _: Duration) {
+                panic!("observer");
+            }
+        }
+
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .observer(Arc::new(Flaky(AtomicUsize::new(0))))
+            .build()
+            .unwrap();
+        let ran = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..4 {
+            let ran = Arc::clone(&ran);
+            pool.execute(move || {
+                ran.fetch_add(1, Ordering::SeqCst);
+            });
+        }
+        pool.wait_idle();
+
+        assert_eq!(ran.load(Ordering::SeqCst), 4);
+        let stats = pool.stats();
+        assert_eq!(stats.completed_jobs, 4);
+        assert_eq!(stats.queued_jobs, 0);
+    }
+}
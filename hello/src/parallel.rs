@@ -0,0 +1,298 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ThreadPool;
+
+/// How many chunks per worker to aim for when the chunk size isn't set, enough to even out
+/// chunks that take different amounts of time without paying for a job per item.
+const CHUNKS_PER_WORKER: usize = 4;
+
+/// Parallel helpers with a fixed chunk size, created by [`ThreadPool::with_chunk_size`].
+///
+/// Each chunk of consecutive items runs as one job, results come back in input order.
+#[derive(Clone, Copy)]
+pub struct Parallel<'pool> {
+    pool: &'pool ThreadPool,
+    chunk_size: Option<usize>,
+}
+
+impl ThreadPool {
+    /// Returns parallel helpers that split their input into chunks of `chunk_size` items.
+    ///
+    /// A chunk size of zero is treated as one.
+    pub fn with_chunk_size(&self, chunk_size: usize) -> Parallel<'_> {
+        Parallel {
+            pool: self,
+            chunk_size: Some(chunk_size.max(1)),
+        }
+    }
+
+    fn parallel(&self) -> Parallel<'_> {
+        Parallel {
+            pool: self,
+            chunk_size: None,
+        }
+    }
+
+    /// Applies `f` to every item on the pool and returns the results in input order.
+    ///
+    /// If `f` panics, the panic is resumed here once the other chunks have finished. Chunks are
+    /// scoped jobs, so on a full bounded queue they're run on the calling thread rather than
+    /// dropped, whatever the pool's [`OverflowPolicy`](crate::OverflowPolicy).
+    pub fn map<I, F, T>(&self, items: I, f: F) -> Vec<T>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> T + Sync,
+        T: Send,
+    {
+        self.parallel().map(items, f)
+    }
+
+    /// Like [`ThreadPool::map`] for a fallible `f`, returns the error for the earliest failing
+    /// item in input order.
+    pub fn try_map<I, F, T, E>(&self, items: I, f: F) -> Result<Vec<T>, E>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> Result<T, E> + Sync,
+        T: Send,
+        E: Send,
+    {
+        self.parallel().try_map(items, f)
+    }
+
+    /// Calls `f` on every item on the pool and waits for all of them.
+    pub fn for_each<I, F>(&self, items: I, f: F)
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) + Sync,
+    {
+        self.parallel().for_each(items, f)
+    }
+
+    /// Like [`ThreadPool::for_each`] for a fallible `f`, returns the error for the earliest
+    /// failing item in input order.
+    pub fn try_for_each<I, F, E>(&self, items: I, f: F) -> Result<(), E>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> Result<(), E> + Sync,
+        E: Send,
+    {
+        self.parallel().try_for_each(items, f)
+    }
+
+    /// Maps every item with `map` and folds the results with `reduce`, which should be
+    /// associative. Returns `None` for empty input.
+    pub fn map_reduce<I, M, R, T>(&self, items: I, map: M, reduce: R) -> Option<T>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        M: Fn(I::Item) -> T + Sync,
+        R: Fn(T, T) -> T + Sync,
+        T: Send,
+    {
+        self.parallel().map_reduce(items, map, reduce)
+    }
+}
+
+impl Parallel<'_> {
+    /// See [`ThreadPool::map`].
+    pub fn map<I, F, T>(&self, items: I, f: F) -> Vec<T>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> T + Sync,
+        T: Send,
+    {
+        self.run_chunks(items, |_, chunk| {
+            chunk.into_iter().map(&f).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// See [`ThreadPool::try_map`].
+    pub fn try_map<I, F, T, E>(&self, items: I, f: F) -> Result<Vec<T>, E>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> Result<T, E> + Sync,
+        T: Send,
+        E: Send,
+    {
+        // Index of the earliest chunk known to have failed, chunks after it aren't worth running
+        let failed_at = AtomicUsize::new(usize::MAX);
+
+        let chunks = self.run_chunks(items, |index, chunk| {
+            if index > failed_at.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            let result = chunk.into_iter().map(&f).collect::<Result<Vec<_>, _>>();
+            if result.is_err() {
+                failed_at.fetch_min(index, Ordering::Relaxed);
+            }
+
+            Some(result)
+        });
+
+        let mut results = Vec::new();
+
+        for chunk in chunks {
+            match chunk {
+                Some(Ok(values)) => results.extend(values),
+                Some(Err(e)) => return Err(e),
+                None => unreachable!("a chunk is only skipped after an earlier one failed"),
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// See [`ThreadPool::for_each`].
+    pub fn for_each<I, F>(&self, items: I, f: F)
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) + Sync,
+    {
+        self.run_chunks(items, |_, chunk| chunk.into_iter().for_each(&f));
+    }
+
+    /// See [`ThreadPool::try_for_each`].
+    pub fn try_for_each<I, F, E>(&self, items: I, f: F) -> Result<(), E>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        F: Fn(I::Item) -> Result<(), E> + Sync,
+        E: Send,
+    {
+        self.try_map(items, f).map(|_| ())
+    }
+
+    /// See [`ThreadPool::map_reduce`].
+    pub fn map_reduce<I, M, R, T>(&self, items: I, map: M, reduce: R) -> Option<T>
+    where
+        I: IntoIterator,
+        I::Item: Send,
+        M: Fn(I::Item) -> T + Sync,
+        R: Fn(T, T) -> T + Sync,
+        T: Send,
+    {
+        self.run_chunks(items, |_, chunk| {
+            chunk.into_iter().map(&map).reduce(&reduce)
+        })
+        .into_iter()
+        .flatten()
+        .reduce(&reduce)
+    }
+
+    /// Splits `items` into chunks, runs `f` on each chunk as a scoped job and returns what each
+    /// call returned, in chunk order.
+    fn run_chunks<T, F, R>(&self, items: impl IntoIterator<Item = T>, f: F) -> Vec<R>
+    where
+        T: Send,
+        F: Fn(usize, Vec<T>) -> R + Sync,
+        R: Send,
+    {
+        let items: Vec<T> = items.into_iter().collect();
+        if items.is_empty() {
+            return Vec::new();
+        }
+
+        let chunk_size = self.chunk_size.unwrap_or_else(|| {
+            let chunks = self.pool.workers.len() * CHUNKS_PER_WORKER;
+            items.len().div_ceil(chunks)
+        });
+
+        let mut chunks: Vec<Vec<T>> = Vec::with_capacity(items.len().div_ceil(chunk_size));
+        let mut items = items.into_iter().peekable();
+        while items.peek().is_some() {
+            chunks.push(items.by_ref().take(chunk_size).collect());
+        }
+
+        let mut results: Vec<Option<R>> = chunks.iter().map(|_| None).collect();
+
+        self.pool.scope(|s| {
+            for (index, (chunk, slot)) in chunks.into_iter().zip(&mut results).enumerate() {
+                let f = &f;
+                s.execute(move || *slot = Some(f(index, chunk)));
+            }
+        });
+
+        results
+            .into_iter()
+            .map(|result| result.expect("scope waits for every chunk"))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::OverflowPolicy;
+
+    #[test]
+    fn map_keeps_input_order() {
+        let pool = ThreadPool::build(3).unwrap();
+
+        let squares = pool.with_chunk_size(2).map(1..=7, |n| n * n);
+
+        assert_eq!(squares, vec![1, 4, 9, 16, 25, 36, 49]);
+    }
+
+    #[test]
+    fn try_map_returns_earliest_error() {
+        let pool = ThreadPool::build(3).unwrap();
+
+        let result = pool
+            .with_chunk_size(1)
+            .try_map(0..20, |n| if n % 7 == 6 { Err(n) } else { Ok(n) });
+
+        assert_eq!(result, Err(6));
+    }
+
+    #[test]
+    fn map_reduce_folds_every_item() {
+        let pool = ThreadPool::build(4).unwrap();
+        let words = ["alpha", "beta", "gamma", "delta", "epsilon"];
+
+        let total = pool.map_reduce(words, str::len, |a, b| a + b);
+
+        assert_eq!(total, Some(26));
+        assert_eq!(
+            pool.map_reduce(Vec::<&str>::new(), str::len, |a, b| a + b),
+            None
+        );
+    }
+
+    #[test]
+    fn every_chunk_runs_on_a_pool_that_drops_jobs() {
+        for policy in [OverflowPolicy::Reject, OverflowPolicy::DropOldest] {
+            let pool = ThreadPool::build_bounded(2, 1, policy).unwrap();
+            let parallel = pool.with_chunk_size(1);
+
+            let doubled = parallel.map(0..100, |n| n * 2);
+            assert_eq!(doubled, (0..100).map(|n| n * 2).collect::<Vec<_>>());
+
+            let seen = AtomicUsize::new(0);
+            parallel.for_each(0..100, |_| {
+                seen.fetch_add(1, Ordering::SeqCst);
+            });
+            assert_eq!(seen.load(Ordering::SeqCst), 100);
+
+            assert_eq!(
+                parallel.try_map(0..100, Ok::<_, ()>).map(|v| v.len()),
+                Ok(100)
+            );
+            assert_eq!(
+                parallel.map_reduce(1..=100, |n| n, |a, b| a + b),
+                Some(5050)
+            );
+            assert_eq!(pool.rejected_jobs(), 0, "{policy:?}");
+        }
+    }
+}
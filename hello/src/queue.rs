@@ -0,0 +1,595 @@
+use std::{
+    cell::Cell,
+    cmp::Ordering as CmpOrdering,
+    collections::{BinaryHeap, VecDeque},
+    mem,
+    sync::{
+        Condvar, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Job, cancel::CancelToken, lock};
+
+/// What [`ThreadPool::execute`](crate::ThreadPool::execute) does when a bounded queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Wait until a worker takes a job off the queue.
+    #[default]
+    Block,
+    /// Drop the new job.
+    Reject,
+    /// Drop the job that has been waiting longest and queue the new one.
+    ///
+    /// Jobs queued through a [`Scope`](crate::Scope) are never dropped, when nothing else is
+    /// queued the new job is dropped instead.
+    DropOldest,
+    /// Run the new job on the calling thread.
+    CallerRuns,
+}
+
+/// How urgently a queued job should run, see
+/// [`ThreadPool::execute_with_priority`](crate::ThreadPool::execute_with_priority).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Priority {
+    /// Background work that can wait behind everything else for a while.
+    Low,
+    /// What [`ThreadPool::execute`](crate::ThreadPool::execute) uses.
+    #[default]
+    Normal,
+    /// Work that should jump the queue, such as health checks.
+    High,
+}
+
+impl Priority {
+    /// How many aging steps a job of this priority is held back behind a `High` one.
+    fn handicap(self) -> u32 {
+        match self {
+            Priority::High => 0,
+            Priority::Normal => 1,
+            Priority::Low => 2,
+        }
+    }
+}
+
+/// Per-job settings that travel with a job through the queue.
+#[derive(Default)]
+pub(crate) struct TaskOptions {
+    /// Token that withdraws the job if it's cancelled before a worker picks the job up.
+    pub(crate) cancel: Option<CancelToken>,
+    pub(crate) priority: Priority,
+    /// Keeps `DropOldest` from evicting the job, for scoped jobs the caller is waiting on.
+    pub(crate) pinned: bool,
+}
+
+/// A job waiting in a [`JobQueue`].
+pub(crate) struct Task {
+    pub(crate) job: Job,
+    pub(crate) queued_at: Instant,
+    pub(crate) options: TaskOptions,
+}
+
+impl Task {
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+    }
+}
+
+/// Outcome of pushing a job onto a [`JobQueue`].
+pub(crate) enum Push<F> {
+    Queued,
+    /// The queue was full and `DropOldest` evicted this job to make room.
+    Evicted(Task),
+    /// The queue is full (or closed) and the policy didn't make room, the job is handed back.
+    Full(F),
+}
+
+/// Default for how long a job has to wait to catch up with jobs one priority level above it.
+pub(crate) const DEFAULT_AGING: Duration = Duration::from_millis(500);
+
+/// How many times an idle worker yields before it parks.
+const IDLE_SPINS: u32 = 32;
+
+thread_local! {
+    /// Address of the queue owned by the pool this thread works for, and the worker's slot in it.
+    static CURRENT: Cell<Option<(usize, usize)>> = const { Cell::new(None) };
+}
+
+/// Global queue for jobs submitted from outside the workers, ordered by priority with aging.
+///
+/// Each job is ranked by the time it was queued plus one aging step per priority level below
+/// `High`, and the lowest rank runs first. A job therefore only waits behind higher priority
+/// jobs that were queued less than a few aging steps after it, so `Low` jobs can't starve.
+struct Injector {
+    heap: BinaryHeap<Ranked>,
+    next_seq: u64,
+    aging: Duration,
+}
+
+struct Ranked {
+    rank: Instant,
+    /// Insertion order, breaks ties between equal ranks and identifies the oldest job.
+    seq: u64,
+    task: Task,
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Ranked) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Ranked) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    // Reversed so the `BinaryHeap` pops the lowest rank first
+    fn cmp(&self, other: &Ranked) -> CmpOrdering {
+        (other.rank, other.seq).cmp(&(self.rank, self.seq))
+    }
+}
+
+impl Injector {
+    fn push(&mut self, task: Task) {
+        let rank = task.queued_at + self.aging * task.options.priority.handicap();
+        let seq = self.next_seq;
+        self.next_seq += 1;
+
+        self.heap.push(Ranked { rank, seq, task });
+    }
+
+    fn pop(&mut self) -> Option<Task> {
+        self.heap.pop().map(|ranked| ranked.task)
+    }
+
+    /// Removes the unpinned job that was queued first regardless of priority, in linear time.
+    fn pop_oldest(&mut self) -> Option<Task> {
+        let mut ranked = mem::take(&mut self.heap).into_vec();
+
+        let oldest = ranked
+            .iter()
+            .enumerate()
+            .filter(|(_, ranked)| !ranked.task.options.pinned)
+            .min_by_key(|(_, ranked)| ranked.seq)
+            .map(|(i, _)| i);
+        let task = oldest.map(|i| ranked.swap_remove(i).task);
+
+        self.heap = BinaryHeap::from(ranked);
+        task
+    }
+
+    /// Removes every job, in the order they would have run.
+    fn drain(&mut self) -> impl Iterator<Item = Task> + use<> {
+        // Sorted ascending by the reversed `Ord`, so the job that would run last comes first
+        let sorted = mem::take(&mut self.heap).into_sorted_vec();
+
+        sorted.into_iter().rev().map(|ranked| ranked.task)
+    }
+}
+
+/// Work-stealing job queue shared by the workers, optionally bounded.
+///
+/// Jobs submitted from outside the pool go onto a global [`Injector`]. `Normal` priority jobs
+/// submitted by a job that's already running on a worker go onto that worker's own deque, which
+/// it pops from the back, unless a `High` job is waiting on the injector. A worker with nothing
+/// local takes from the injector and then steals from the front of the other workers' deques.
+/// No lock is held while a worker waits for work, so idle workers don't get in the way of busy
+/// ones the way a shared `Mutex<Receiver>` does.
+pub(crate) struct JobQueue {
+    injector: Mutex<Injector>,
+    locals: Vec<Mutex<VecDeque<Task>>>,
+    capacity: Option<usize>,
+    /// Jobs queued anywhere, plus slots reserved by pushes that are still in progress.
+    queued: AtomicUsize,
+    /// `High` priority jobs on the injector, so workers can check for them without its lock.
+    high: AtomicUsize,
+    closed: AtomicBool,
+    /// Workers waiting for a job.
+    idle: Sleepers,
+    /// Producers waiting for room under `OverflowPolicy::Block`.
+    blocked: Sleepers,
+}
+
+impl JobQueue {
+    pub(crate) fn new(workers: usize, capacity: Option<usize>, aging: Duration) -> JobQueue {
+        JobQueue {
+            injector: Mutex::new(Injector {
+                heap: BinaryHeap::new(),
+                next_seq: 0,
+                aging,
+            }),
+            locals: (0..workers).map(|_| Mutex::new(VecDeque::new())).collect(),
+            capacity,
+            queued: AtomicUsize::new(0),
+            high: AtomicUsize::new(0),
+            closed: AtomicBool::new(false),
+            idle: Sleepers::new(),
+            blocked: Sleepers::new(),
+        }
+    }
+
+    fn key(&self) -> usize {
+        self as *const JobQueue as usize
+    }
+
+    /// Marks the calling thread as worker `id` of this queue.
+    pub(crate) fn register_worker(&self, id: usize) {
+        CURRENT.set(Some((self.key(), id)));
+    }
+
+    fn current_worker(&self) -> Option<usize> {
+        CURRENT
+            .get()
+            .and_then(|(key, id)| (key == self.key()).then_some(id))
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::SeqCst)
+    }
+
+    fn has_room(&self) -> bool {
+        self.capacity
+            .is_none_or(|capacity| self.queued.load(Ordering::SeqCst) < capacity)
+    }
+
+    fn try_reserve(&self) -> bool {
+        match self.capacity {
+            None => {
+                self.queued.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            Some(capacity) => self
+                .queued
+                .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                    (n < capacity).then_some(n + 1)
+                })
+                .is_ok(),
+        }
+    }
+
+    fn release(&self) {
+        self.queued.fetch_sub(1, Ordering::SeqCst);
+        self.blocked.notify_one();
+    }
+
+    /// Queues `f`, applying `policy` if the queue is full.
+    ///
+    /// `CallerRuns` and `Reject` both hand the job back as [`Push::Full`], it's up to the caller
+    /// to run or drop it.
+    pub(crate) fn push<F>(&self, f: F, options: TaskOptions, policy: OverflowPolicy) -> Push<F>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        let mut evicted = None;
+
+        loop {
+            if self.is_closed() {
+                // Only reachable while the pool is being dropped, an evicted job goes with it
+                return Push::Full(f);
+            }
+
+            if self.try_reserve() {
+                // Checked again after reserving so a worker can't see an empty, closed queue and
+                // exit while this job is on its way in
+                if self.is_closed() {
+                    self.release();
+                    return Push::Full(f);
+                }
+
+                self.push_reserved(Task {
+                    job: Box::new(f),
+                    queued_at: Instant::now(),
+                    options,
+                });
+
+                return match evicted {
+                    Some(job) => Push::Evicted(job),
+                    None => Push::Queued,
+                };
+            }
+
+            match policy {
+                OverflowPolicy::Block => self
+                    .blocked
+                    .wait_until(|| self.has_room() || self.is_closed()),
+                OverflowPolicy::DropOldest if evicted.is_none() => match self.pop_oldest() {
+                    Some(job) => {
+                        evicted = Some(job);
+                        self.release();
+                    }
+                    // Only scoped jobs are queued, they have to run so the new job goes instead
+                    None if self.holds_pinned() => return Push::Full(f),
+                    // Every counted slot belongs to a push that hasn't landed yet
+                    None => thread::yield_now(),
+                },
+                // Another producer took the slot freed by eviction, keep trying for one
+                OverflowPolicy::DropOldest => thread::yield_now(),
+                OverflowPolicy::Reject | OverflowPolicy::CallerRuns => return Push::Full(f),
+            }
+        }
+    }
+
+    fn push_reserved(&self, task: Task) {
+        // Local deques are plain FIFOs, only the injector knows about priorities
+        match self.current_worker() {
+            Some(id) if task.options.priority == Priority::Normal => {
+                lock(&self.locals[id]).push_back(task)
+            }
+            _ => {
+                if task.options.priority == Priority::High {
+                    self.high.fetch_add(1, Ordering::SeqCst);
+                }
+                lock(&self.injector).push(task);
+            }
+        }
+
+        self.idle.notify_one();
+    }
+
+    /// Keeps `high` in step with a task taken off the injector.
+    fn taken_from_injector(&self, task: Option<Task>) -> Option<Task> {
+        if let Some(task) = &task
+            && task.options.priority == Priority::High
+        {
+            self.high.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        task
+    }
+
+    fn pop_oldest(&self) -> Option<Task> {
+        let oldest = lock(&self.injector).pop_oldest();
+
+        self.taken_from_injector(oldest).or_else(|| {
+            self.locals.iter().find_map(|local| {
+                let mut local = lock(local);
+                let oldest = local.iter().position(|task| !task.options.pinned)?;
+
+                local.remove(oldest)
+            })
+        })
+    }
+
+    fn holds_pinned(&self) -> bool {
+        let pinned = |task: &Task| task.options.pinned;
+
+        let injector = lock(&self.injector);
+        if injector.heap.iter().any(|ranked| pinned(&ranked.task)) {
+            return true;
+        }
+        drop(injector);
+
+        self.locals
+            .iter()
+            .any(|local| lock(local).iter().any(pinned))
+    }
+
+    fn find_task(&self, id: usize) -> Option<Task> {
+        // `High` jobs from outside go ahead of the backlog running jobs left on this worker
+        if self.high.load(Ordering::SeqCst) > 0
+            && let Some(task) = self.pop_injector()
+        {
+            return Some(task);
+        }
+
+        if let Some(task) = lock(&self.locals[id]).pop_back() {
+            return Some(task);
+        }
+
+        if let Some(task) = self.pop_injector() {
+            return Some(task);
+        }
+
+        let workers = self.locals.len();
+
+        (1..workers).find_map(|offset| lock(&self.locals[(id + offset) % workers]).pop_front())
+    }
+
+    fn pop_injector(&self) -> Option<Task> {
+        let task = lock(&self.injector).pop();
+
+        self.taken_from_injector(task)
+    }
+
+    /// Blocks worker `id` until a job is available, returns `None` once the queue is closed and
+    /// empty.
+    pub(crate) fn pop(&self, id: usize) -> Option<Task> {
+        let mut spins = 0;
+
+        loop {
+            if let Some(task) = self.find_task(id) {
+                self.release();
+                return Some(task);
+            }
+
+            if self.queued.load(Ordering::SeqCst) > 0 {
+                // A push has reserved its slot but not landed the job yet
+                thread::yield_now();
+            } else if self.is_closed() {
+                return None;
+            } else if spins < IDLE_SPINS {
+                // Short jobs tend to arrive in bursts, parking and waking up for each one costs
+                // more than giving up the CPU a few times first
+                spins += 1;
+                thread::yield_now();
+            } else {
+                spins = 0;
+                self.idle
+                    .wait_until(|| self.queued.load(Ordering::SeqCst) > 0 || self.is_closed());
+            }
+        }
+    }
+
+    /// Number of jobs waiting to start.
+    pub(crate) fn len(&self) -> usize {
+        self.queued.load(Ordering::SeqCst)
+    }
+
+    /// Takes every job that hasn't started or been cancelled, roughly in the order they would
+    /// have run.
+    pub(crate) fn drain(&self) -> Vec<Job> {
+        let mut tasks: Vec<Task> = lock(&self.injector).drain().collect();
+        let high = tasks
+            .iter()
+            .filter(|task| task.options.priority == Priority::High)
+            .count();
+        self.high.fetch_sub(high, Ordering::SeqCst);
+
+        for local in &self.locals {
+            tasks.extend(lock(local).drain(..));
+        }
+
+        self.queued.fetch_sub(tasks.len(), Ordering::SeqCst);
+        self.blocked.notify_all();
+
+        // Cancelled jobs were withdrawn by their owner, they're not worth handing back
+        tasks
+            .into_iter()
+            .filter(|task| !task.is_cancelled())
+            .map(|task| task.job)
+            .collect()
+    }
+
+    /// Stops accepting jobs, workers drain what's left and then see `None` from `pop`.
+    ///
+    /// Returns `false` if the queue was already closed.
+    pub(crate) fn close(&self) -> bool {
+        let was_open = !self.closed.swap(true, Ordering::SeqCst);
+
+        self.idle.notify_all();
+        self.blocked.notify_all();
+
+        was_open
+    }
+}
+
+/// Threads parked until some condition on the queue's atomics holds.
+///
+/// Notifiers only touch the lock when someone is waiting, which keeps it off the fast path.
+struct Sleepers {
+    lock: Mutex<()>,
+    cvar: Condvar,
+    waiting: AtomicUsize,
+}
+
+impl Sleepers {
+    fn new() -> Sleepers {
+        Sleepers {
+            lock: Mutex::new(()),
+            cvar: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Parks until notified unless `ready` already holds, callers re-check in a loop.
+    fn wait_until(&self, ready: impl Fn() -> bool) {
+        let guard = lock(&self.lock);
+
+        // Announce before checking: a notifier that changes the state after our check will see
+        // `waiting` and take the lock, which it can only get once we're parked in `wait`
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+
+        if !ready() {
+            drop(
+                self.cvar
+                    .wait(guard)
+                    .unwrap_or_else(PoisonError::into_inner),
+            );
+        } else {
+            drop(guard);
+        }
+
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn notify_one(&self) {
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            let _guard = lock(&self.lock);
+            self.cvar.notify_one();
+        }
+    }
+
+    fn notify_all(&self) {
+        if self.waiting.load(Ordering::SeqCst) > 0 {
+            let _guard = lock(&self.lock);
+            self.cvar.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ThreadPool, tests::parked};
+    use std::sync::{Arc, mpsc};
+
+    #[test]
+    fn higher_priority_jobs_run_first_with_aging() {
+        let pool = ThreadPool::builder()
+            .num_threads(1)
+            .priority_aging(Duration::from_millis(20))
+            .build()
+            .unwrap();
+        let release_tx = parked(&pool);
+        let (order_tx, order_rx) = mpsc::channel();
+
+        let submit = |priority, name: &'static str| {
+            let order_tx = order_tx.clone();
+            pool.execute_with_priority(priority, move || order_tx.send(name).unwrap());
+        };
+
+        submit(Priority::Low, "old low");
+        thread::sleep(Duration::from_millis(50));
+        submit(Priority::Low, "low");
+        submit(Priority::Normal, "normal");
+        submit(Priority::High, "high");
+        drop(order_tx);
+
+        release_tx.send(()).unwrap();
+
+        // The old low job has waited more than two aging steps, so it's ahead of everything
+        assert_eq!(
+            order_rx.iter().collect::<Vec<_>>(),
+            vec!["old low", "high", "normal", "low"]
+        );
+    }
+
+    #[test]
+    fn high_priority_jobs_go_ahead_of_a_workers_local_backlog() {
+        let pool = Arc::new(ThreadPool::build(1).unwrap());
+        let (order_tx, order_rx) = mpsc::channel();
+        let (queued_tx, queued_rx) = mpsc::channel();
+        let (release_tx, release_rx) = mpsc::channel::<()>();
+
+        // Jobs queued from the worker land on its own deque
+        let inner = Arc::clone(&pool);
+        let local_tx = order_tx.clone();
+        pool.execute(move || {
+            for _ in 0..3 {
+                let tx = local_tx.clone();
+                inner.execute(move || tx.send("local").unwrap());
+            }
+            queued_tx.send(()).unwrap();
+            release_rx.recv().unwrap();
+        });
+        queued_rx.recv().unwrap();
+
+        pool.execute_with_priority(Priority::High, move || order_tx.send("high").unwrap());
+        release_tx.send(()).unwrap();
+
+        assert_eq!(
+            order_rx.iter().collect::<Vec<_>>(),
+            vec!["high", "local", "local", "local"]
+        );
+    }
+}
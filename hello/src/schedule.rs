@@ -0,0 +1,315 @@
+use std::{
+    cmp::Ordering as CmpOrdering,
+    collections::BinaryHeap,
+    io,
+    sync::{
+        Arc, Condvar, Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Job, Shared, ThreadPool, lock};
+
+/// Handle to a job scheduled with [`ThreadPool::execute_after`], [`ThreadPool::execute_at`] or
+/// [`ThreadPool::execute_every`].
+///
+/// Dropping the handle doesn't cancel the job.
+#[derive(Debug, Clone)]
+pub struct ScheduledJob {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ScheduledJob {
+    /// Stops the job from being handed to the pool again.
+    ///
+    /// A run that has already been queued on the pool, or is running, isn't affected.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns `true` if [`ScheduledJob::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+enum Work {
+    Once(Job),
+    Every {
+        f: Arc<dyn Fn() + Send + Sync + 'static>,
+        period: Duration,
+    },
+}
+
+struct Entry {
+    deadline: Instant,
+    /// Breaks ties between equal deadlines in scheduling order.
+    seq: u64,
+    cancelled: Arc<AtomicBool>,
+    work: Work,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == CmpOrdering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    // Reversed so the `BinaryHeap` pops the earliest deadline first
+    fn cmp(&self, other: &Entry) -> CmpOrdering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+struct TimerState {
+    entries: BinaryHeap<Entry>,
+    next_seq: u64,
+    stopped: bool,
+}
+
+struct TimerShared {
+    state: Mutex<TimerState>,
+    changed: Condvar,
+}
+
+/// Thread that sleeps until the next scheduled deadline and then queues the job on the pool.
+pub(crate) struct Timer {
+    shared: Arc<TimerShared>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl Timer {
+    fn start(pool: Arc<Shared>) -> io::Result<Timer> {
+        let shared = Arc::new(TimerShared {
+            state: Mutex::new(TimerState {
+                entries: BinaryHeap::new(),
+                next_seq: 0,
+                stopped: false,
+            }),
+            changed: Condvar::new(),
+        });
+
+        let timer = Arc::clone(&shared);
+        let thread = thread::Builder::new().spawn(move || timer.run(&pool))?;
+
+        Ok(Timer { shared, thread })
+    }
+
+    fn schedule(&self, deadline: Instant, work: Work) -> ScheduledJob {
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        let mut state = lock(&self.shared.state);
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        state.entries.push(Entry {
+            deadline,
+            seq,
+            cancelled: Arc::clone(&cancelled),
+            work,
+        });
+        drop(state);
+
+        self.shared.changed.notify_one();
+
+        ScheduledJob { cancelled }
+    }
+
+    /// Stops the timer thread, jobs that haven't come due are dropped.
+    pub(crate) fn stop(self) {
+        lock(&self.shared.state).stopped = true;
+        self.shared.changed.notify_one();
+
+        let _ = self.thread.join();
+    }
+}
+
+impl TimerShared {
+    fn run(&self, pool: &Shared) {
+        let mut state = lock(&self.state);
+
+        loop {
+            if state.stopped {
+                return;
+            }
+
+            let now = Instant::now();
+
+            let deadline = match state.entries.peek() {
+                None => None,
+                Some(entry) if entry.deadline <= now => {
+                    let entry = state.entries.pop().expect("peeked entry is there");
+
+                    if entry.cancelled.load(Ordering::SeqCst) {
+                        continue;
+                    }
+
+                    let job = match entry.work {
+                        Work::Once(job) => job,
+                        Work::Every { f, period } => {
+                            // Keep to the original schedule unless we've fallen a whole period
+                            // behind, then start again from now rather than firing in a burst
+                            let mut next = entry.deadline + period;
+                            if next <= now {
+                                next = now + period;
+                            }
+
+                            let seq = state.next_seq;
+                            state.next_seq += 1;
+
+                            state.entries.push(Entry {
+                                deadline: next,
+                                seq,
+                                cancelled: entry.cancelled,
+                                work: Work::Every {
+                                    f: Arc::clone(&f),
+                                    period,
+                                },
+                            });
+
+                            Box::new(move || f())
+                        }
+                    };
+
+                    // Queueing can block under `OverflowPolicy::Block`, don't hold up callers
+                    // scheduling new jobs meanwhile
+                    drop(state);
+                    pool.execute(job);
+                    state = lock(&self.state);
+
+                    continue;
+                }
+                Some(entry) => Some(entry.deadline),
+            };
+
+            state = match deadline {
+                None => self
+                    .changed
+                    .wait(state)
+                    .unwrap_or_else(PoisonError::into_inner),
+                Some(deadline) => {
+                    self.changed
+                        .wait_timeout(state, deadline - now)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0
+                }
+            };
+        }
+    }
+}
+
+impl ThreadPool {
+    fn timer(&self) -> &Timer {
+        self.timer.get_or_init(|| {
+            Timer::start(Arc::clone(&self.shared)).expect("failed to spawn thread pool timer")
+        })
+    }
+
+    /// Queues `f` on the pool once `delay` has passed.
+    ///
+    /// Scheduling is handled by a timer thread that's started the first time any of the
+    /// `execute_after`, `execute_at` or `execute_every` methods is called. Jobs that haven't
+    /// come due when the pool shuts down are dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the timer thread has to be started and can't be spawned
+    pub fn execute_after<F>(&self, delay: Duration, f: F) -> ScheduledJob
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.execute_at(Instant::now() + delay, f)
+    }
+
+    /// Queues `f` on the pool at `instant`, or straight away if it has already passed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the timer thread has to be started and can't be spawned
+    pub fn execute_at<F>(&self, instant: Instant, f: F) -> ScheduledJob
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.timer().schedule(instant, Work::Once(Box::new(f)))
+    }
+
+    /// Queues `f` on the pool every `period`, starting one period from now, until cancelled.
+    ///
+    /// Runs aren't waited on before the next one is queued, so a run that takes longer than
+    /// `period` can overlap with the next one.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero, or if the timer thread has to be started and can't be spawned
+    pub fn execute_every<F>(&self, period: Duration, f: F) -> ScheduledJob
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        assert!(!period.is_zero(), "period must be greater than zero");
+
+        self.timer().schedule(
+            Instant::now() + period,
+            Work::Every {
+                f: Arc::new(f),
+                period,
+            },
+        )
+    }
+
+    /// Stops the timer thread if it was ever started.
+    pub(crate) fn stop_timer(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.stop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    #[test]
+    fn scheduled_jobs_run_after_their_delay_until_cancelled() {
+        let pool = ThreadPool::build(2).unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        let start = Instant::now();
+        let once_tx = tx.clone();
+        pool.execute_after(Duration::from_millis(30), move || {
+            once_tx.send("once").unwrap()
+        });
+
+        let cancelled_tx = tx.clone();
+        pool.execute_after(Duration::from_millis(10), move || {
+            cancelled_tx.send("cancelled").unwrap()
+        })
+        .cancel();
+
+        let tx = Mutex::new(tx);
+        let every = pool.execute_every(Duration::from_millis(5), move || {
+            let _ = lock(&tx).send("every");
+        });
+
+        let mut seen = Vec::new();
+        while !seen.contains(&"once") {
+            seen.push(rx.recv().unwrap());
+        }
+        every.cancel();
+
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert!(!seen.contains(&"cancelled"));
+        assert!(seen.iter().filter(|&&s| s == "every").count() >= 2);
+    }
+}
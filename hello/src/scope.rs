@@ -0,0 +1,241 @@
+use std::{
+    any::Any,
+    marker::PhantomData,
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    OverflowPolicy, ThreadPool,
+    group::Latch,
+    lock,
+    queue::{Push, TaskOptions},
+};
+
+/// Scope for jobs that borrow from the caller's stack, created by [`ThreadPool::scope`].
+pub struct Scope<'scope, 'env: 'scope> {
+    pool: &'scope ThreadPool,
+    state: Arc<ScopeState>,
+    // Invariant over both lifetimes, for the same reasons as `std::thread::Scope`
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+
+struct ScopeState {
+    pending: Latch,
+    panic: Mutex<Option<Box<dyn Any + Send + 'static>>>,
+}
+
+/// Counts a job as finished when dropped, whether it ran or the queue turned it away.
+struct Pending {
+    state: Arc<ScopeState>,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.state.pending.done();
+    }
+}
+
+/// A scoped job as it sits in the pool's queue.
+///
+/// Fields drop in declaration order, so the closure and its borrows are gone before `pending`
+/// tells the scope it's safe to return.
+struct ScopedJob<F> {
+    f: Option<F>,
+    pending: Pending,
+}
+
+impl<F: FnOnce()> ScopedJob<F> {
+    fn run(mut self) {
+        let f = self.f.take().expect("scoped job runs once");
+
+        if let Err(payload) = panic::catch_unwind(AssertUnwindSafe(f)) {
+            lock(&self.pending.state.panic).get_or_insert(payload);
+        }
+    }
+}
+
+impl<'scope> Scope<'scope, '_> {
+    /// Queues `f` on the pool, `f` may borrow anything that outlives the scope. If the queue is
+    /// full, see [`ThreadPool::scope`].
+    ///
+    /// If `f` panics, the panic is held until every job in the scope has finished and then
+    /// resumed from [`ThreadPool::scope`].
+    pub fn execute<F>(&'scope self, f: F)
+    where
+        F: FnOnce() + Send + 'scope,
+    {
+        self.state.pending.add();
+
+        let job = ScopedJob {
+            f: Some(f),
+            pending: Pending {
+                state: Arc::clone(&self.state),
+            },
+        };
+        let job: Box<dyn FnOnce() + Send + 'scope> = Box::new(move || job.run());
+
+        // SAFETY: `ThreadPool::scope` doesn't return, or unwind, until `pending` is back to
+        // zero, and `pending` is only decremented once the job has been dropped, whether it ran
+        // or the queue dropped it. The pool is borrowed for the whole scope so it can't be shut
+        // down or dropped in between. Nothing the job borrows can go away while it's queued.
+        let job: Box<dyn FnOnce() + Send + 'static> = unsafe { mem::transmute(job) };
+
+        // A full queue runs the job here rather than dropping it, `Block` still waits for room.
+        // Pinned so `DropOldest` doesn't evict it to make room for someone else's job either.
+        let policy = match self.pool.shared.policy {
+            OverflowPolicy::Block => OverflowPolicy::Block,
+            _ => OverflowPolicy::Reject,
+        };
+        let options = TaskOptions {
+            pinned: true,
+            ..TaskOptions::default()
+        };
+        match self.pool.shared.push(job, options, policy) {
+            Push::Queued => {}
+            Push::Evicted(_) => unreachable!("scoped jobs never evict a queued job"),
+            Push::Full(job) => job(),
+        }
+    }
+}
+
+impl ThreadPool {
+    /// Runs `f` with a [`Scope`] whose jobs can borrow non-`'static` data, like
+    /// [`std::thread::scope`] does for threads.
+    ///
+    /// Every job queued through the scope has finished by the time this returns. If `f` or any
+    /// of the jobs panicked, the panic is resumed here once they're all done.
+    ///
+    /// Scoped jobs are never dropped. When the queue is bounded and full they follow the
+    /// pool's [`OverflowPolicy`] only if it's `Block`, under any other policy the job runs on
+    /// the thread calling [`Scope::execute`], as with `CallerRuns`. Once queued, `DropOldest`
+    /// skips them when making room for other jobs.
+    ///
+    /// Waiting blocks the calling thread, so calling this from a job on the same pool can
+    /// deadlock if every worker ends up waiting on a scope.
+    pub fn scope<'env, F, T>(&self, f: F) -> T
+    where
+        F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+    {
+        let scope = Scope {
+            pool: self,
+            state: Arc::new(ScopeState {
+                pending: Latch::new(),
+                panic: Mutex::new(None),
+            }),
+            scope: PhantomData,
+            env: PhantomData,
+        };
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| f(&scope)));
+
+        scope.state.pending.wait();
+
+        match result {
+            Err(payload) => panic::resume_unwind(payload),
+            Ok(value) => match lock(&scope.state.panic).take() {
+                Some(payload) => panic::resume_unwind(payload),
+                None => value,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[test]
+    fn scoped_jobs_borrow_from_the_stack() {
+        let pool = ThreadPool::build(3).unwrap();
+        let mut chunks = vec![vec![1, 2], vec![3, 4], vec![5, 6]];
+        let offset = 10;
+
+        pool.scope(|s| {
+            for chunk in chunks.iter_mut() {
+                s.execute(|| chunk.iter_mut().for_each(|n| *n += offset));
+            }
+        });
+
+        assert_eq!(chunks, vec![vec![11, 12], vec![13, 14], vec![15, 16]]);
+    }
+
+    #[test]
+    fn scope_waits_for_jobs_before_resuming_a_panic() {
+        let pool = ThreadPool::build(2).unwrap();
+        let finished = AtomicBool::new(false);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            pool.scope(|s| {
+                s.execute(|| panic!("scoped"));
+                s.execute(|| {
+                    thread::sleep(Duration::from_millis(20));
+                    finished.store(true, Ordering::SeqCst);
+                });
+            })
+        }));
+
+        assert!(result.is_err());
+        assert!(finished.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn scoped_jobs_run_on_the_caller_when_the_queue_is_full() {
+        for policy in [OverflowPolicy::Reject, OverflowPolicy::DropOldest] {
+            let pool = ThreadPool::build_bounded(1, 1, policy).unwrap();
+            let ran = AtomicUsize::new(0);
+
+            pool.scope(|s| {
+                for _ in 0..50 {
+                    s.execute(|| {
+                        thread::sleep(Duration::from_millis(1));
+                        ran.fetch_add(1, Ordering::SeqCst);
+                    });
+                }
+            });
+
+            assert_eq!(ran.load(Ordering::SeqCst), 50, "{policy:?}");
+            assert_eq!(pool.rejected_jobs(), 0, "{policy:?}");
+        }
+    }
+
+    #[test]
+    fn drop_oldest_producers_never_evict_scoped_jobs() {
+        let pool = ThreadPool::build_bounded(1, 2, OverflowPolicy::DropOldest).unwrap();
+        let stop = AtomicBool::new(false);
+
+        let ran = thread::scope(|threads| {
+            threads.spawn(|| {
+                while !stop.load(Ordering::SeqCst) {
+                    pool.execute(|| {});
+                }
+            });
+
+            let ran: Vec<usize> = (0..100)
+                .map(|_| {
+                    let ran = AtomicUsize::new(0);
+                    pool.scope(|s| {
+                        for _ in 0..20 {
+                            s.execute(|| {
+                                ran.fetch_add(1, Ordering::SeqCst);
+                            });
+                        }
+                    });
+                    ran.into_inner()
+                })
+                .collect();
+
+            stop.store(true, Ordering::SeqCst);
+            ran
+        });
+
+        assert!(ran.iter().all(|&n| n == 20), "{ran:?}");
+    }
+}
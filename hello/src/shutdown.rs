@@ -0,0 +1,129 @@
+use std::{
+    error::Error,
+    fmt, mem,
+    sync::PoisonError,
+    time::{Duration, Instant},
+};
+
+use crate::{Job, ThreadPool, lock};
+
+/// Error returned by [`ThreadPool::shutdown`] when some workers were still busy at the deadline.
+#[derive(Debug)]
+pub struct ShutdownTimeout {
+    missed: Vec<usize>,
+}
+
+impl ShutdownTimeout {
+    /// Ids of the workers that hadn't finished by the deadline.
+    pub fn missed_workers(&self) -> &[usize] {
+        &self.missed
+    }
+}
+
+impl fmt::Display for ShutdownTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} worker(s) missed the shutdown deadline: {:?}",
+            self.missed.len(),
+            self.missed
+        )
+    }
+}
+
+impl Error for ShutdownTimeout {}
+
+impl ThreadPool {
+    /// Stops accepting jobs and waits up to `timeout` for the workers to drain the queue and exit.
+    ///
+    /// Workers that are still running at the deadline are detached rather than joined, they exit
+    /// on their own once the queue is empty, and their ids are returned in the error.
+    pub fn shutdown(mut self, timeout: Duration) -> Result<(), ShutdownTimeout> {
+        self.close();
+
+        let deadline = Instant::now() + timeout;
+        let mut exited = lock(&self.shared.exited);
+
+        while exited.iter().any(|done| !done) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+
+            exited = self
+                .shared
+                .worker_exited
+                .wait_timeout(exited, remaining)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+
+        let exited = exited.clone();
+        let mut missed = Vec::new();
+
+        // Taking the workers leaves nothing for `Drop` to block on
+        for worker in mem::take(&mut self.workers) {
+            if exited[worker.id] {
+                worker.join();
+            } else {
+                missed.push(worker.id);
+            }
+        }
+
+        if missed.is_empty() {
+            Ok(())
+        } else {
+            Err(ShutdownTimeout { missed })
+        }
+    }
+
+    /// Stops accepting jobs and returns the ones that haven't started yet, so they can be
+    /// persisted or retried.
+    ///
+    /// Jobs that are already running are left to finish, this blocks until they have and the
+    /// workers have been joined.
+    pub fn shutdown_now(mut self) -> Vec<Job> {
+        self.close();
+
+        // `Drop` joins the workers once they've finished whatever they were running
+        self.shared.queue.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parked;
+    use std::thread;
+
+    #[test]
+    fn shutdown_reports_workers_past_the_deadline() {
+        let pool = ThreadPool::build(2).unwrap();
+        let release_tx = parked(&pool);
+        pool.execute(|| {});
+
+        let err = pool.shutdown(Duration::from_millis(50)).unwrap_err();
+        assert_eq!(err.missed_workers().len(), 1);
+
+        release_tx.send(()).unwrap();
+    }
+
+    #[test]
+    fn shutdown_now_returns_jobs_that_never_started() {
+        let pool = ThreadPool::build(1).unwrap();
+        let release_tx = parked(&pool);
+
+        for _ in 0..3 {
+            pool.execute(|| {});
+        }
+
+        // `shutdown_now` waits for the running job, so it has to be released from elsewhere
+        let releaser = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            release_tx.send(()).unwrap();
+        });
+
+        assert_eq!(pool.shutdown_now().len(), 3);
+        releaser.join().unwrap();
+    }
+}
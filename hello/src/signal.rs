@@ -0,0 +1,200 @@
+use std::{
+    io,
+    net::{SocketAddr, TcpListener, TcpStream},
+};
+
+#[cfg(target_os = "linux")]
+use std::os::fd::AsRawFd;
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        ffi::{c_int, c_short, c_ulong, c_void},
+        fs::File,
+        io,
+        os::fd::{AsRawFd, FromRawFd},
+        sync::atomic::{AtomicI32, Ordering},
+    };
+
+    pub(super) const SIGINT: c_int = 2;
+    pub(super) const SIGTERM: c_int = 15;
+
+    const O_NONBLOCK: c_int = 0o4000;
+    const O_CLOEXEC: c_int = 0o2000000;
+    const POLLIN: c_short = 1;
+    const SIG_ERR: usize = usize::MAX;
+
+    #[repr(C)]
+    pub(super) struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    unsafe extern "C" {
+        fn pipe2(fds: *mut c_int, flags: c_int) -> c_int;
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+        fn poll(fds: *mut PollFd, nfds: c_ulong, timeout: c_int) -> c_int;
+    }
+
+    /// Write end of the self-pipe, -1 until a handler is installed.
+    static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    /// Runs in signal context, so it does nothing but write a byte to the pipe, which is
+    /// async-signal-safe. The pipe is non-blocking, if it's full a wakeup is already pending.
+    extern "C" fn on_signal(_signum: c_int) {
+        let fd = PIPE.load(Ordering::SeqCst);
+
+        if fd >= 0 {
+            let byte = 1u8;
+
+            // SAFETY: the buffer is a live local of the length passed, and `write` on an invalid
+            // fd fails instead of touching memory
+            unsafe {
+                write(fd, (&raw const byte).cast(), 1);
+            }
+        }
+    }
+
+    /// Creates the self-pipe and routes `signals` to it, returns the read end.
+    pub(super) fn install(signals: &[c_int]) -> io::Result<File> {
+        let mut fds = [-1; 2];
+
+        // SAFETY: `fds` has room for the two descriptors `pipe2` writes
+        if unsafe { pipe2(fds.as_mut_ptr(), O_NONBLOCK | O_CLOEXEC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // SAFETY: `pipe2` succeeded, so both are open descriptors that nothing else owns
+        let (read, write) = unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+
+        if PIPE
+            .compare_exchange(-1, write.as_raw_fd(), Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "shutdown signal handler already installed",
+            ));
+        }
+
+        // The handler can fire at any point from here on, so the write end stays open for the
+        // rest of the process
+        std::mem::forget(write);
+
+        for &signum in signals {
+            // SAFETY: `on_signal` only does async-signal-safe work
+            if unsafe { signal(signum, on_signal) } == SIG_ERR {
+                return Err(io::Error::last_os_error());
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Blocks until one of `fds` is readable, returns which ones are.
+    pub(super) fn wait_readable<const N: usize>(fds: [c_int; N]) -> io::Result<[bool; N]> {
+        let mut polled = fds.map(|fd| PollFd {
+            fd,
+            events: POLLIN,
+            revents: 0,
+        });
+
+        loop {
+            // SAFETY: `polled` is an array of `N` initialized entries that outlives the call
+            if unsafe { poll(polled.as_mut_ptr(), N as c_ulong, -1) } >= 0 {
+                return Ok(polled.map(|fd| fd.revents != 0));
+            }
+
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+}
+
+/// Notice of SIGINT or SIGTERM, delivered to an accept loop so a server can stop cleanly.
+///
+/// The signal handler writes a byte to a pipe (the self-pipe trick), and
+/// [`ShutdownSignal::accept`] waits on that pipe and the listener together, so a signal wakes
+/// it even while no connections are coming in.
+///
+/// Only supported on Linux. Elsewhere installing succeeds but does nothing, `accept` never
+/// reports a signal and the signals keep their default behaviour.
+#[derive(Debug)]
+pub struct ShutdownSignal {
+    #[cfg(target_os = "linux")]
+    pipe: std::fs::File,
+}
+
+impl ShutdownSignal {
+    /// Installs handlers for SIGINT and SIGTERM.
+    ///
+    /// Fails with [`io::ErrorKind::AlreadyExists`] if called more than once in a process.
+    #[cfg(target_os = "linux")]
+    pub fn install() -> io::Result<ShutdownSignal> {
+        let pipe = sys::install(&[sys::SIGINT, sys::SIGTERM])?;
+
+        Ok(ShutdownSignal { pipe })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn install() -> io::Result<ShutdownSignal> {
+        Ok(ShutdownSignal {})
+    }
+
+    /// Waits for the next connection on `listener`, or returns `None` once a signal has
+    /// arrived, including one that arrived before this was called.
+    #[cfg(target_os = "linux")]
+    pub fn accept(&self, listener: &TcpListener) -> io::Result<Option<(TcpStream, SocketAddr)>> {
+        let [signalled, _] = sys::wait_readable([self.pipe.as_raw_fd(), listener.as_raw_fd()])?;
+
+        if signalled {
+            return Ok(None);
+        }
+
+        listener.accept().map(Some)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn accept(&self, listener: &TcpListener) -> io::Result<Option<(TcpStream, SocketAddr)>> {
+        listener.accept().map(Some)
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::{ffi::c_int, thread, time::Duration};
+
+    unsafe extern "C" {
+        safe fn raise(signum: c_int) -> c_int;
+    }
+
+    #[test]
+    fn signals_stop_the_accept_loop() {
+        let signal = ShutdownSignal::install().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = TcpStream::connect(addr).unwrap();
+        assert!(signal.accept(&listener).unwrap().is_some());
+
+        let raiser = thread::spawn(|| {
+            thread::sleep(Duration::from_millis(50));
+            assert_eq!(raise(sys::SIGTERM), 0);
+        });
+
+        assert!(signal.accept(&listener).unwrap().is_none());
+        raiser.join().unwrap();
+
+        // A signal stays pending, later connections aren't handed out any more
+        let _late = TcpStream::connect(addr).unwrap();
+        assert!(signal.accept(&listener).unwrap().is_none());
+
+        let err = ShutdownSignal::install().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    sync::atomic::{AtomicU64, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+/// Number of histogram buckets, the first holds durations under 1µs and each one after that
+/// doubles the upper bound, so the last finite bucket ends at 2^29µs, about 9 minutes.
+const BUCKETS: usize = 31;
+
+/// Point-in-time view of a [`ThreadPool`](crate::ThreadPool), returned by
+/// [`ThreadPool::stats`](crate::ThreadPool::stats).
+///
+/// The counters are read one at a time while the pool keeps running, so they may not add up
+/// exactly against each other.
+#[derive(Debug, Clone)]
+pub struct PoolStats {
+    /// Jobs waiting for a worker.
+    pub queued_jobs: usize,
+    /// Workers currently running a job.
+    pub active_workers: usize,
+    /// Workers waiting for a job.
+    pub idle_workers: usize,
+    /// Jobs that ran to completion.
+    pub completed_jobs: usize,
+    /// Jobs that panicked.
+    pub panicked_jobs: usize,
+    /// Jobs dropped because the queue was full.
+    pub rejected_jobs: usize,
+    /// Jobs dropped because they were cancelled before they started.
+    pub cancelled_jobs: usize,
+    /// How long jobs waited in the queue before a worker picked them up.
+    pub queue_wait: Histogram,
+    /// How long jobs took to run.
+    pub run_time: Histogram,
+}
+
+/// Distribution of durations over power-of-two buckets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    counts: [u64; BUCKETS],
+    total: Duration,
+}
+
+impl Histogram {
+    /// Number of recorded durations.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Average of the recorded durations, or zero if there are none.
+    pub fn mean(&self) -> Duration {
+        match self.count() {
+            0 => Duration::ZERO,
+            count => self.total.div_f64(count as f64),
+        }
+    }
+
+    /// Upper bound of the bucket holding the `p`th percentile, with `p` between 0 and 100.
+    ///
+    /// Returns `None` if nothing has been recorded or the percentile falls in the last bucket,
+    /// which has no upper bound.
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+
+        let target = ((p.clamp(0.0, 100.0) / 100.0) * count as f64)
+            .ceil()
+            .max(1.0) as u64;
+        let mut seen = 0;
+
+        for (i, n) in self.counts.iter().enumerate() {
+            seen += n;
+
+            if seen >= target {
+                return upper_bound(i);
+            }
+        }
+
+        None
+    }
+
+    /// Non-empty buckets as `(upper bound, count)` pairs, with `None` for the unbounded last one.
+    pub fn buckets(&self) -> impl Iterator<Item = (Option<Duration>, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &n)| n > 0)
+            .map(|(i, &n)| (upper_bound(i), n))
+    }
+}
+
+fn upper_bound(bucket: usize) -> Option<Duration> {
+    (bucket < BUCKETS - 1).then(|| Duration::from_micros(1 << bucket))
+}
+
+fn bucket_for(duration: Duration) -> usize {
+    let micros = duration.as_micros();
+
+    if micros == 0 {
+        0
+    } else {
+        // Bucket `i` holds durations below `2^i` µs
+        ((u128::BITS - micros.leading_zeros()) as usize).min(BUCKETS - 1)
+    }
+}
+
+/// Lock-free histogram the workers record into.
+struct AtomicHistogram {
+    counts: [AtomicU64; BUCKETS],
+    total_nanos: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            total_nanos: AtomicU64::new(0),
+        }
+    }
+
+    fn record(&self, duration: Duration) {
+        self.counts[bucket_for(duration)].fetch_add(1, Ordering::Relaxed);
+
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        self.total_nanos.fetch_add(nanos, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> Histogram {
+        Histogram {
+            counts: std::array::from_fn(|i| self.counts[i].load(Ordering::Relaxed)),
+            total: Duration::from_nanos(self.total_nanos.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// Counters the workers update as they run jobs.
+pub(crate) struct Metrics {
+    active_workers: AtomicUsize,
+    completed_jobs: AtomicUsize,
+    panicked_jobs: AtomicUsize,
+    rejected_jobs: AtomicUsize,
+    cancelled_jobs: AtomicUsize,
+    queue_wait: AtomicHistogram,
+    run_time: AtomicHistogram,
+}
+
+impl Metrics {
+    pub(crate) fn new() -> Metrics {
+        Metrics {
+            active_workers: AtomicUsize::new(0),
+            completed_jobs: AtomicUsize::new(0),
+            panicked_jobs: AtomicUsize::new(0),
+            rejected_jobs: AtomicUsize::new(0),
+            cancelled_jobs: AtomicUsize::new(0),
+            queue_wait: AtomicHistogram::new(),
+            run_time: AtomicHistogram::new(),
+        }
+    }
+
+    pub(crate) fn job_started(&self, waited: Duration) {
+        self.queue_wait.record(waited);
+        self.active_workers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn job_finished(&self, ran: Duration, panicked: bool) {
+        self.run_time.record(ran);
+        self.active_workers.fetch_sub(1, Ordering::Relaxed);
+
+        if panicked {
+            self.panicked_jobs.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.completed_jobs.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn job_rejected(&self) {
+        self.rejected_jobs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn job_cancelled(&self) {
+        self.cancelled_jobs.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn panicked_jobs(&self) -> usize {
+        self.panicked_jobs.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn rejected_jobs(&self) -> usize {
+        self.rejected_jobs.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn snapshot(&self, workers: usize, queued_jobs: usize) -> PoolStats {
+        let active_workers = self.active_workers.load(Ordering::Relaxed).min(workers);
+
+        PoolStats {
+            queued_jobs,
+            active_workers,
+            idle_workers: workers - active_workers,
+            completed_jobs: self.completed_jobs.load(Ordering::Relaxed),
+            panicked_jobs: self.panicked_jobs(),
+            rejected_jobs: self.rejected_jobs(),
+            cancelled_jobs: self.cancelled_jobs.load(Ordering::Relaxed),
+            queue_wait: self.queue_wait.snapshot(),
+            run_time: self.run_time.snapshot(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ThreadPool;
+    use std::thread;
+
+    #[test]
+    fn durations_land_in_power_of_two_buckets() {
+        assert_eq!(bucket_for(Duration::from_nanos(500)), 0);
+        assert_eq!(bucket_for(Duration::from_micros(1)), 1);
+        assert_eq!(bucket_for(Duration::from_micros(3)), 2);
+        assert_eq!(bucket_for(Duration::from_micros(4)), 3);
+        assert_eq!(bucket_for(Duration::from_secs(100_000)), BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentile_reports_bucket_upper_bound() {
+        let histogram = AtomicHistogram::new();
+        for micros in [1, 1, 1, 100] {
+            histogram.record(Duration::from_micros(micros));
+        }
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 4);
+        assert_eq!(snapshot.percentile(50.0), Some(Duration::from_micros(2)));
+        assert_eq!(snapshot.percentile(100.0), Some(Duration::from_micros(128)));
+    }
+
+    #[test]
+    fn stats_track_jobs_and_timings() {
+        let pool = ThreadPool::build(1).unwrap();
+
+        pool.execute(|| {});
+        pool.execute(|| thread::sleep(Duration::from_millis(5)));
+        pool.execute(|| panic!("counted"));
+
+        // With one worker, everything above has been recorded once this job has started
+        pool.spawn(|| {}).join().unwrap();
+
+        let stats = pool.stats();
+        assert!(stats.completed_jobs >= 2);
+        assert_eq!(stats.panicked_jobs, 1);
+        assert_eq!(stats.queued_jobs, 0);
+        assert!(stats.run_time.count() >= 3);
+        assert!(stats.run_time.percentile(100.0).unwrap() >= Duration::from_millis(5));
+    }
+}